@@ -0,0 +1,191 @@
+/*
+ * Copyright 2019-2020 Wren Powell
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Content-defined chunking for deduplicating data written to an archive.
+//!
+//! A [`Chunker`] splits an incoming byte stream into variable-length chunks at content-defined
+//! boundaries rather than fixed offsets. Because the boundaries are a function of the bytes
+//! themselves, the same run of bytes produces the same chunk wherever it appears: across files,
+//! and across successive versions of the same file. Each chunk is hashed to a [`BlockId`], and a
+//! chunk only needs to be written to the `DataStore` the first time its `BlockId` is seen.
+//!
+//! [`Header::from_tar`](crate::header::Header::from_tar) drives a [`Chunker`] this way today. The
+//! `FileArchive`/`Archive` path in [`crate::file::archive`] was the chunker's original intended
+//! consumer, but `Archive`, `DataHandle`, `ArchiveObject`, and `EntryType` are crate-root types
+//! with no corresponding source file in this tree (there is no `lib.rs`), and `DataHandle` is a
+//! single opaque handle rather than the ordered list of `BlockId`s this chunker would need to
+//! return — so wiring dedup into `FileArchive::write` isn't possible without first introducing
+//! those types, which is out of scope here.
+
+use std::io::{self, Read};
+
+/// A BLAKE3 hash identifying the contents of a chunk.
+///
+/// Two chunks with the same contents always have the same `BlockId`, which is what allows
+/// identical chunks to be deduplicated across files.
+pub type BlockId = [u8; 32];
+
+/// The default minimum chunk size in bytes.
+pub const DEFAULT_MIN_CHUNK_SIZE: usize = 16 * 1024;
+
+/// The default average chunk size in bytes.
+pub const DEFAULT_AVG_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// The default maximum chunk size in bytes.
+pub const DEFAULT_MAX_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
+/// Parameters controlling how data is split into content-defined chunks.
+///
+/// Smaller chunks improve deduplication granularity at the cost of more metadata overhead per
+/// byte stored; larger chunks do the opposite. `avg_size` is approximate: actual chunk sizes form
+/// a distribution around it, bounded by `min_size` and `max_size`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkerConfig {
+    /// The minimum size of a chunk in bytes.
+    ///
+    /// No boundary is considered before a chunk reaches this size, which bounds the number of
+    /// chunks (and thus the metadata overhead) produced by pathological inputs.
+    pub min_size: usize,
+
+    /// The approximate average size of a chunk in bytes.
+    pub avg_size: usize,
+
+    /// The maximum size of a chunk in bytes.
+    ///
+    /// A boundary is forced here even if the rolling hash never finds one, which bounds the
+    /// amount of data buffered in memory before a chunk is flushed.
+    pub max_size: usize,
+}
+
+impl Default for ChunkerConfig {
+    fn default() -> Self {
+        Self {
+            min_size: DEFAULT_MIN_CHUNK_SIZE,
+            avg_size: DEFAULT_AVG_CHUNK_SIZE,
+            max_size: DEFAULT_MAX_CHUNK_SIZE,
+        }
+    }
+}
+
+impl ChunkerConfig {
+    /// The bitmask applied to the rolling hash to test for a chunk boundary.
+    ///
+    /// A boundary is cut when `hash & mask == 0`, which happens on average once every
+    /// `mask + 1` bytes.
+    fn boundary_mask(&self) -> u64 {
+        let bits = (self.avg_size.max(2) as f64).log2().round() as u32;
+        (1u64 << bits) - 1
+    }
+}
+
+/// Splits a byte stream into content-defined chunks.
+///
+/// This uses a gear-hash rolling hash: each incoming byte shifts the hash and mixes in a
+/// per-byte value from a fixed table, so the hash depends only on a trailing window of bytes. A
+/// boundary is cut whenever the low bits of the hash are all zero and the chunk has reached
+/// `min_size`, or unconditionally once it reaches `max_size`.
+pub struct Chunker {
+    config: ChunkerConfig,
+    mask: u64,
+    buffer: Vec<u8>,
+    hash: u64,
+}
+
+impl Chunker {
+    /// Create a new `Chunker` using the given `config`.
+    pub fn new(config: ChunkerConfig) -> Self {
+        let mask = config.boundary_mask();
+        Self {
+            config,
+            mask,
+            buffer: Vec::with_capacity(config.max_size.min(1024 * 1024)),
+            hash: 0,
+        }
+    }
+
+    /// Read all of `reader`, invoking `on_chunk` with each chunk's bytes and its `BlockId`.
+    ///
+    /// `on_chunk` is responsible for deciding whether the chunk needs to be written to the
+    /// `DataStore`; a typical implementation calls `read_block` first and skips the write if the
+    /// block is already present.
+    ///
+    /// # Errors
+    /// - `io::Error`: An I/O error occurred reading from `reader`, or `on_chunk` returned an
+    /// error.
+    pub fn chunk(
+        &mut self,
+        mut reader: impl Read,
+        mut on_chunk: impl FnMut(&[u8], BlockId) -> io::Result<()>,
+    ) -> io::Result<()> {
+        self.buffer.clear();
+        self.hash = 0;
+
+        let mut read_buffer = [0u8; 8192];
+        loop {
+            let bytes_read = reader.read(&mut read_buffer)?;
+            if bytes_read == 0 {
+                break;
+            }
+
+            for &byte in &read_buffer[..bytes_read] {
+                self.buffer.push(byte);
+                self.hash = (self.hash << 1).wrapping_add(gear_value(byte));
+
+                let at_min = self.buffer.len() >= self.config.min_size;
+                let at_boundary = at_min && self.hash & self.mask == 0;
+                let at_max = self.buffer.len() >= self.config.max_size;
+
+                if at_boundary || at_max {
+                    self.flush_chunk(&mut on_chunk)?;
+                }
+            }
+        }
+
+        if !self.buffer.is_empty() {
+            self.flush_chunk(&mut on_chunk)?;
+        }
+
+        Ok(())
+    }
+
+    fn flush_chunk(
+        &mut self,
+        on_chunk: &mut impl FnMut(&[u8], BlockId) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let id = hash_chunk(&self.buffer);
+        on_chunk(&self.buffer, id)?;
+        self.buffer.clear();
+        self.hash = 0;
+        Ok(())
+    }
+}
+
+/// Hash a chunk's contents to derive its `BlockId`.
+fn hash_chunk(data: &[u8]) -> BlockId {
+    *blake3::hash(data).as_bytes()
+}
+
+/// Derive this byte's entry in the gear-hash table.
+///
+/// Rather than storing a 256-entry table of precomputed random values, we derive each entry on
+/// the fly with SplitMix64, seeded by the byte value itself. This is deterministic, has no
+/// per-process state, and is cheap enough to call per byte.
+fn gear_value(byte: u8) -> u64 {
+    let mut z = (byte as u64).wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,347 @@
+/*
+ * Copyright 2019 Garrett Powell
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! A metadata-preserving directory archiving subsystem built on top of `ObjectArchive`.
+//!
+//! `ObjectArchive` only knows how to store opaque byte objects under string keys; it has no
+//! notion of a filesystem tree. `DirArchive` adds that layer: archiving a directory captures each
+//! entry's type, POSIX permissions, modification time, ownership, and extended attributes
+//! alongside its content, and restoring can selectively reapply any subset of that metadata.
+
+use std::collections::HashMap;
+use std::fs::{self, create_dir_all, File};
+use std::io;
+use std::os::unix::fs::{symlink, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use filetime::{set_symlink_file_times, FileTime};
+use rmp_serde::{from_slice, to_vec};
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use crate::tar::{link_target_is_contained, sanitize_entry_path};
+use crate::{ArchiveConfig, ObjectArchive};
+
+/// The key under which the archive's entry manifest is stored.
+///
+/// This name can never collide with an archived path: every archived key is a path read from
+/// the filesystem, and no filesystem path contains a NUL byte.
+const MANIFEST_KEY: &str = "\u{0}manifest";
+
+/// A single extended attribute captured from a file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtendedAttribute {
+    /// The name of the attribute.
+    pub name: String,
+
+    /// The value of the attribute.
+    pub value: Vec<u8>,
+}
+
+/// The type of filesystem entry a [`EntryMetadata`] describes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum EntryKind {
+    /// A regular file. Its content is stored under the entry's path in the underlying
+    /// `ObjectArchive`.
+    File,
+
+    /// A directory.
+    Directory,
+
+    /// A symbolic link pointing at `target`.
+    Symlink { target: PathBuf },
+}
+
+/// Metadata captured for a single archived filesystem entry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntryMetadata {
+    /// The type of entry, and any type-specific data.
+    pub kind: EntryKind,
+
+    /// The entry's POSIX permissions bits.
+    pub mode: u32,
+
+    /// The time the entry was last modified.
+    pub modified: SystemTime,
+
+    /// The ID of the user which owns the entry.
+    pub uid: u32,
+
+    /// The ID of the group which owns the entry.
+    pub gid: u32,
+
+    /// The entry's extended attributes.
+    pub xattrs: Vec<ExtendedAttribute>,
+}
+
+/// The manifest of every entry in a `DirArchive`, keyed by sanitized relative path.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Manifest {
+    entries: HashMap<String, EntryMetadata>,
+}
+
+/// Options controlling which recorded metadata is reapplied when restoring a `DirArchive`.
+///
+/// These mirror the knobs `FileArchive`'s `ExtractOptions` exposes for tar-style extraction. The
+/// defaults preserve everything that was recorded except ownership, and never overwrite an
+/// existing file.
+#[derive(Debug, Clone, Copy)]
+pub struct RestoreOptions {
+    /// Replace an existing file or symlink at the destination path. An existing directory is
+    /// reused for a directory entry; restoring any other kind of entry over it fails.
+    pub overwrite: bool,
+
+    /// Apply the entry's recorded POSIX permissions bits.
+    pub preserve_permissions: bool,
+
+    /// Apply the entry's recorded modification time.
+    pub preserve_mtime: bool,
+
+    /// Apply the entry's recorded extended attributes.
+    pub unpack_xattrs: bool,
+
+    /// Change the owning user and group of the restored entry to its recorded `uid` and `gid`.
+    ///
+    /// This requires the process to have permission to change ownership, which typically means
+    /// running as root.
+    pub preserve_ownerships: bool,
+}
+
+impl Default for RestoreOptions {
+    fn default() -> Self {
+        Self {
+            overwrite: false,
+            preserve_permissions: true,
+            preserve_mtime: true,
+            unpack_xattrs: true,
+            preserve_ownerships: false,
+        }
+    }
+}
+
+/// A metadata-preserving archive of a directory tree, backed by an `ObjectArchive`.
+pub struct DirArchive {
+    objects: ObjectArchive<String>,
+    manifest: Manifest,
+}
+
+impl DirArchive {
+    /// Create a new `DirArchive` at `path` with the given `config`.
+    ///
+    /// # Errors
+    /// - `ErrorKind::AlreadyExists`: There is already a file at `path`.
+    pub fn create(path: &Path, config: ArchiveConfig, password: Option<&[u8]>) -> io::Result<Self> {
+        let objects = ObjectArchive::create(path, config, password)?;
+        Ok(Self {
+            objects,
+            manifest: Manifest::default(),
+        })
+    }
+
+    /// Open the existing `DirArchive` at `path`.
+    ///
+    /// # Errors
+    /// - `ErrorKind::NotFound`: There is no archive at `path`.
+    /// - `ErrorKind::InvalidData`: The data at `path` is not a valid archive, or its manifest is
+    /// corrupt.
+    pub fn open(path: &Path, password: Option<&[u8]>) -> io::Result<Self> {
+        let objects = ObjectArchive::open(path, password)?;
+        let manifest = match objects.get(&MANIFEST_KEY.to_string()) {
+            Some(object) => {
+                let bytes = objects.read_all(object)?;
+                from_slice(&bytes)
+                    .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "the archive's manifest is corrupt"))?
+            }
+            None => Manifest::default(),
+        };
+        Ok(Self { objects, manifest })
+    }
+
+    /// Archive the directory tree rooted at `source`, recording each entry's metadata.
+    ///
+    /// Each entry is stored under its path relative to `source`; the same sanitization used by
+    /// `ObjectArchive::import_tar` rejects any path that could escape this namespace.
+    ///
+    /// # Errors
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn archive_tree(&mut self, source: &Path) -> io::Result<()> {
+        for result in WalkDir::new(source).into_iter() {
+            let dir_entry = result?;
+            let relative = dir_entry.path().strip_prefix(source).expect("Walked entry is not under `source`.");
+            if relative.as_os_str().is_empty() {
+                continue;
+            }
+
+            let sanitized = sanitize_entry_path(relative)?;
+            let key = sanitized.to_string_lossy().into_owned();
+
+            let metadata = fs::symlink_metadata(dir_entry.path())?;
+            let file_type = metadata.file_type();
+
+            let kind = if file_type.is_symlink() {
+                EntryKind::Symlink {
+                    target: fs::read_link(dir_entry.path())?,
+                }
+            } else if file_type.is_dir() {
+                EntryKind::Directory
+            } else {
+                let object = self.objects.write(File::open(dir_entry.path())?)?;
+                self.objects.insert(key.clone(), object);
+                EntryKind::File
+            };
+
+            let xattrs = xattr::list(dir_entry.path())?
+                .filter_map(|name| {
+                    let value = xattr::get(dir_entry.path(), &name).ok().flatten()?;
+                    Some(ExtendedAttribute {
+                        name: name.to_string_lossy().into_owned(),
+                        value,
+                    })
+                })
+                .collect();
+
+            self.manifest.entries.insert(
+                key,
+                EntryMetadata {
+                    kind,
+                    mode: metadata.mode() & 0o7777,
+                    modified: metadata.modified()?,
+                    uid: metadata.uid(),
+                    gid: metadata.gid(),
+                    xattrs,
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Restore the archived tree to `dest`, applying `options`.
+    ///
+    /// Directories are created first, followed by symlinks and regular files, so that a
+    /// symlink's parent directory always exists by the time it's created.
+    ///
+    /// # Errors
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn restore_tree(&self, dest: &Path, options: &RestoreOptions) -> io::Result<()> {
+        let mut paths = self.manifest.entries.keys().cloned().collect::<Vec<_>>();
+        paths.sort();
+
+        for key in &paths {
+            let entry = &self.manifest.entries[key];
+            if matches!(entry.kind, EntryKind::Directory) {
+                self.restore_entry(dest, key, entry, options)?;
+            }
+        }
+        for key in &paths {
+            let entry = &self.manifest.entries[key];
+            if !matches!(entry.kind, EntryKind::Directory) {
+                self.restore_entry(dest, key, entry, options)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Restore a single entry to `dest`, applying `options`.
+    fn restore_entry(
+        &self,
+        dest: &Path,
+        key: &str,
+        entry: &EntryMetadata,
+        options: &RestoreOptions,
+    ) -> io::Result<()> {
+        let sanitized = sanitize_entry_path(Path::new(key))?;
+        let entry_dest = dest.join(sanitized);
+
+        if entry_dest.exists() && !options.overwrite {
+            return Ok(());
+        }
+
+        match &entry.kind {
+            EntryKind::Directory => {
+                create_dir_all(&entry_dest)?;
+            }
+            EntryKind::Symlink { target } => {
+                if !link_target_is_contained(Path::new(key), target) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("symlink `{}` targets `{}`, which escapes the archive", key, target.display()),
+                    ));
+                }
+
+                if entry_dest.exists() {
+                    fs::remove_file(&entry_dest)?;
+                }
+                if let Some(parent) = entry_dest.parent() {
+                    create_dir_all(parent)?;
+                }
+                symlink(target, &entry_dest)?;
+            }
+            EntryKind::File => {
+                if let Some(parent) = entry_dest.parent() {
+                    create_dir_all(parent)?;
+                }
+                let object = self
+                    .objects
+                    .get(&key.to_string())
+                    .expect("Manifest entry has no associated object.");
+                let data = self.objects.read_all(object)?;
+                // Remove an existing symlink first, so the write replaces it instead of following it.
+                if fs::symlink_metadata(&entry_dest)
+                    .map(|metadata| metadata.file_type().is_symlink())
+                    .unwrap_or(false)
+                {
+                    fs::remove_file(&entry_dest)?;
+                }
+                fs::write(&entry_dest, data)?;
+            }
+        }
+
+        if options.preserve_permissions && !matches!(entry.kind, EntryKind::Symlink { .. }) {
+            fs::set_permissions(&entry_dest, fs::Permissions::from_mode(entry.mode))?;
+        }
+        if options.preserve_mtime {
+            let mtime = FileTime::from_system_time(entry.modified);
+            set_symlink_file_times(&entry_dest, mtime, mtime)?;
+        }
+        if options.unpack_xattrs {
+            for xattr in &entry.xattrs {
+                xattr::set(&entry_dest, &xattr.name, &xattr.value)?;
+            }
+        }
+        if options.preserve_ownerships {
+            // `lchown`, not `chown`: the entry may be a symlink, and `chown` follows symlinks,
+            // which would change ownership of whatever the (possibly attacker-controlled) target
+            // points to rather than the link itself.
+            std::os::unix::fs::lchown(&entry_dest, Some(entry.uid), Some(entry.gid))?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the archive's manifest and commit all changes to `self`'s underlying storage.
+    ///
+    /// # Errors
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn commit(&mut self) -> io::Result<()> {
+        let serialized = to_vec(&self.manifest).expect("Could not serialize the archive's manifest.");
+        let object = self.objects.write(serialized.as_slice())?;
+        self.objects.insert(MANIFEST_KEY.to_string(), object);
+        self.objects.commit()
+    }
+}
@@ -0,0 +1,116 @@
+/*
+ * Copyright 2019-2020 Wren Powell
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! The error type shared by every part of this crate.
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// The result type returned by most operations in this crate.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// An error that occurred in this crate.
+#[derive(Debug)]
+pub enum Error {
+    /// The repository or archive is corrupt.
+    ///
+    /// This is returned when data that is expected to be valid, such as a header, can't be read
+    /// back at all. It is usually unrecoverable.
+    Corrupt,
+
+    /// Deserializing a value failed.
+    Deserialize(rmp_serde::decode::Error),
+
+    /// A directory or one of its ancestors has insecure permissions.
+    ///
+    /// The path of the offending directory is included.
+    Insecure(PathBuf),
+
+    /// Ciphertext verification failed.
+    InvalidData,
+
+    /// The given savepoint is not valid for this repository, either because it belongs to a
+    /// different repository or because it has expired.
+    InvalidSavepoint,
+
+    /// An I/O error occurred.
+    Io(io::Error),
+
+    /// A long-running operation was cancelled before it could finish.
+    Cancelled,
+
+    /// The repository's on-disk format predates the current format and must be migrated before
+    /// it can be opened.
+    OutOfDate,
+
+    /// Serializing a value failed.
+    Serialize(rmp_serde::encode::Error),
+
+    /// An error occurred in the backing data store.
+    Store(io::Error),
+
+    /// The repository's on-disk format is newer than this version of the crate understands.
+    UnsupportedFormat,
+
+    /// An error occurred walking a directory tree.
+    Walk(walkdir::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Corrupt => write!(f, "the repository or archive is corrupt"),
+            Self::Deserialize(error) => write!(f, "error deserializing value: {}", error),
+            Self::Insecure(path) => write!(f, "`{}` has insecure permissions", path.display()),
+            Self::InvalidData => write!(f, "ciphertext verification failed"),
+            Self::InvalidSavepoint => write!(f, "the savepoint is not valid for this repository"),
+            Self::Io(error) => write!(f, "I/O error: {}", error),
+            Self::Cancelled => write!(f, "the operation was cancelled"),
+            Self::OutOfDate => write!(f, "the on-disk format must be migrated before it can be opened"),
+            Self::Serialize(error) => write!(f, "error serializing value: {}", error),
+            Self::Store(error) => write!(f, "error in the backing data store: {}", error),
+            Self::UnsupportedFormat => write!(f, "the on-disk format is newer than this crate understands"),
+            Self::Walk(error) => write!(f, "error walking directory tree: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<rmp_serde::decode::Error> for Error {
+    fn from(error: rmp_serde::decode::Error) -> Self {
+        Self::Deserialize(error)
+    }
+}
+
+impl From<rmp_serde::encode::Error> for Error {
+    fn from(error: rmp_serde::encode::Error) -> Self {
+        Self::Serialize(error)
+    }
+}
+
+impl From<walkdir::Error> for Error {
+    fn from(error: walkdir::Error) -> Self {
+        Self::Walk(error)
+    }
+}
@@ -18,22 +18,108 @@ use std::collections::HashMap;
 use std::fs::{
     create_dir, create_dir_all, read_dir, read_link, symlink_metadata, DirEntry, File, OpenOptions,
 };
-use std::io::{self, copy, ErrorKind, Read};
+use std::io::{self, copy, ErrorKind, Read, Write};
 use std::iter;
+use std::os::unix::fs::{chown, MetadataExt};
 use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
 
 use filetime::{set_file_mtime, FileTime};
 use relative_path::RelativePath;
 use rmp_serde::{decode, encode};
+use tar::{Builder as TarBuilder, EntryType as TarEntryType, Header as TarHeader};
 use walkdir::WalkDir;
+use zip::read::ZipArchive;
+use zip::write::{FileOptions, ZipWriter};
+use zip::CompressionMethod;
 
 use crate::error::Result;
 use crate::file::platform::{set_extended_attrs, set_file_mode, soft_link};
 use crate::{Archive, ArchiveObject, DataHandle, EntryType};
 
-use super::entry::ArchiveEntry;
+use super::entry::{ArchiveEntry, ExtendedAttribute};
 use super::platform::{extended_attrs, file_mode};
 
+/// The prefix used for extended attribute names in PAX extended header records.
+const XATTR_PAX_PREFIX: &str = "SCHILY.xattr.";
+
+/// Options controlling how metadata is applied when extracting entries from a `FileArchive`.
+///
+/// These mirror the knobs the `tar` crate exposes on its entries. The defaults preserve
+/// everything that was recorded except ownership, and never overwrite an existing file.
+#[derive(Debug, Clone, Copy)]
+pub struct ExtractOptions {
+    /// Replace an existing file or symlink at the destination path. An existing directory is
+    /// reused for a directory entry; extracting any other kind of entry over it fails.
+    pub overwrite: bool,
+
+    /// Apply the entry's recorded POSIX permissions bits, masked by `mask`.
+    pub preserve_permissions: bool,
+
+    /// Apply the entry's recorded modification time.
+    pub preserve_mtime: bool,
+
+    /// Apply the entry's recorded extended attributes.
+    pub unpack_xattrs: bool,
+
+    /// Change the owning user and group of the extracted file to the entry's recorded `uid` and
+    /// `gid`.
+    ///
+    /// This requires the process to have permission to change ownership, which typically means
+    /// running as root.
+    pub preserve_ownerships: bool,
+
+    /// A mask applied to the entry's permissions bits before they are set on the extracted file.
+    pub mask: i32,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        Self {
+            overwrite: false,
+            preserve_permissions: true,
+            preserve_mtime: true,
+            unpack_xattrs: true,
+            preserve_ownerships: false,
+            mask: !0,
+        }
+    }
+}
+
+/// A standard archive format that a `FileArchive` can be imported from or exported to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    /// A POSIX ustar archive using PAX extended headers to store metadata ustar can't represent.
+    Tar,
+
+    /// A zip archive.
+    Zip,
+}
+
+/// Format one extended attribute as a PAX extended header record.
+///
+/// A PAX record has the form `"<length> <key>=<value>\n"`, where `<length>` is the length of the
+/// whole record, including itself. Because the length of the length prefix can itself affect the
+/// total length, we have to solve for it iteratively.
+fn pax_record(key: &str, value: &[u8]) -> Vec<u8> {
+    let suffix_len = key.len() + value.len() + 3; // b' ', b'=', and b'\n'
+    let mut total_len = suffix_len + 1;
+    loop {
+        let digits = total_len.to_string().len();
+        let candidate = digits + suffix_len;
+        if candidate.to_string().len() == digits {
+            total_len = candidate;
+            break;
+        }
+        total_len = candidate;
+    }
+
+    let mut record = format!("{} {}=", total_len, key).into_bytes();
+    record.extend_from_slice(value);
+    record.push(b'\n');
+    record
+}
+
 impl ArchiveObject {
     /// Convert this object into an entry.
     fn to_entry(&self) -> ArchiveEntry {
@@ -151,6 +237,11 @@ impl FileArchive {
     /// The returned handle can be used to manually construct an `ArchiveEntry` that represents a
     /// regular file.
     ///
+    /// This does not run the content-defined chunker in [`crate::chunking`]: `DataHandle` is a
+    /// single opaque handle owned by `Archive`, not the ordered list of chunk `BlockId`s the
+    /// chunker produces, so there's nowhere in this method to attach per-chunk dedup without
+    /// changing `Archive`'s own representation.
+    ///
     /// # Errors
     /// - `Error::Io`: An I/O error occurred.
     pub fn write(&mut self, source: &mut impl Read) -> Result<DataHandle> {
@@ -189,6 +280,8 @@ impl FileArchive {
         let entry = ArchiveEntry {
             modified_time: metadata.modified()?,
             permissions: file_mode(&metadata),
+            uid: Some(metadata.uid()),
+            gid: Some(metadata.gid()),
             attributes: extended_attrs(&source)?,
             entry_type,
         };
@@ -220,9 +313,30 @@ impl FileArchive {
     ///
     /// This does not remove the `source` entry from the archive.
     ///
+    /// This uses the default `ExtractOptions`, which does not overwrite an existing file at
+    /// `dest` and does not attempt to change ownership. To customize this behavior, use
+    /// [`extract_with`].
+    ///
     /// # Errors
     /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`extract_with`]: FileArchive::extract_with
     pub fn extract(&mut self, source: &RelativePath, dest: &Path) -> Result<()> {
+        self.extract_with(source, dest, &ExtractOptions::default())
+    }
+
+    /// Create a file at `dest` from the archive entry at `source`, applying `options`.
+    ///
+    /// This does not remove the `source` entry from the archive.
+    ///
+    /// # Errors
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn extract_with(
+        &mut self,
+        source: &RelativePath,
+        dest: &Path,
+        options: &ExtractOptions,
+    ) -> Result<()> {
         let entry = match self.entry(source) {
             Some(value) => value,
             None => {
@@ -235,26 +349,53 @@ impl FileArchive {
             create_dir_all(parent)?
         }
 
+        if options.overwrite {
+            match entry.entry_type {
+                EntryType::Directory => {}
+                _ => {
+                    let _ = std::fs::remove_file(dest);
+                }
+            }
+        }
+
         // Create the file, directory, or symlink.
         match entry.entry_type {
             EntryType::File { data } => {
-                let mut file = OpenOptions::new().write(true).create_new(true).open(dest)?;
+                let mut open_options = OpenOptions::new();
+                open_options.write(true);
+                if options.overwrite {
+                    open_options.create(true).truncate(true);
+                } else {
+                    open_options.create_new(true);
+                }
+                let mut file = open_options.open(dest)?;
                 copy(&mut self.read(&data)?, &mut file)?;
             }
             EntryType::Directory => {
-                create_dir(dest)?;
+                if !options.overwrite || !dest.is_dir() {
+                    create_dir(dest)?;
+                }
             }
             EntryType::Link { target } => {
                 soft_link(dest, &target)?;
             }
         }
 
-        // Set the file metadata.
-        set_file_mtime(dest, FileTime::from_system_time(entry.modified_time))?;
-        if let Some(mode) = entry.permissions {
-            set_file_mode(dest, mode)?;
+        // Set the file metadata according to `options`.
+        if options.preserve_mtime {
+            set_file_mtime(dest, FileTime::from_system_time(entry.modified_time))?;
+        }
+        if options.preserve_permissions {
+            if let Some(mode) = entry.permissions {
+                set_file_mode(dest, mode & options.mask)?;
+            }
+        }
+        if options.unpack_xattrs {
+            set_extended_attrs(dest, entry.attributes)?;
+        }
+        if options.preserve_ownerships {
+            chown(dest, entry.uid, entry.gid)?;
         }
-        set_extended_attrs(dest, entry.attributes)?;
 
         Ok(())
     }
@@ -263,9 +404,30 @@ impl FileArchive {
     ///
     /// This does not remove the `source` entry or its descendants from the archive.
     ///
+    /// This uses the default `ExtractOptions`. To customize this behavior, use
+    /// [`extract_tree_with`].
+    ///
     /// # Errors
     /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`extract_tree_with`]: FileArchive::extract_tree_with
     pub fn extract_tree(&mut self, source: &RelativePath, dest: &Path) -> Result<()> {
+        self.extract_tree_with(source, dest, &ExtractOptions::default())
+    }
+
+    /// Create a directory tree at `dest` from the tree of archive entries at `source`, applying
+    /// `options` to each entry.
+    ///
+    /// This does not remove the `source` entry or its descendants from the archive.
+    ///
+    /// # Errors
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn extract_tree_with(
+        &mut self,
+        source: &RelativePath,
+        dest: &Path,
+        options: &ExtractOptions,
+    ) -> Result<()> {
         // We must convert to owned paths because we'll need a mutable reference to `self` later.
         let mut descendants = self
             .walk(source)
@@ -278,7 +440,7 @@ impl FileArchive {
 
         for entry_path in descendants {
             let file_path = entry_path.to_path(dest);
-            self.extract(entry_path.as_relative_path(), file_path.as_path())?;
+            self.extract_with(entry_path.as_relative_path(), file_path.as_path(), options)?;
         }
 
         Ok(())
@@ -305,4 +467,279 @@ impl FileArchive {
             archive: self.archive.compacted(dest)?,
         })
     }
+
+    /// Mount this archive as a read-only FUSE filesystem at `mountpoint`.
+    ///
+    /// Directories are listed via `list`, regular files are read lazily through `Archive::read`,
+    /// and symlinks resolve to their recorded target. This call blocks until the filesystem is
+    /// unmounted.
+    ///
+    /// # Errors
+    /// - `Error::Io`: An I/O error occurred mounting the filesystem.
+    #[cfg(feature = "fuse")]
+    pub fn mount(self, mountpoint: &Path) -> Result<()> {
+        let filesystem = super::fuse::ArchiveFilesystem::new(self);
+        fuser::mount2(filesystem, mountpoint, &[]).map_err(Into::into)
+    }
+
+    /// Import entries from the archive in `reader` in the given `format`.
+    ///
+    /// Each member of the source archive is streamed through `write` to produce a `DataHandle`
+    /// and inserted at the path recorded in its header. Existing entries at the same path are
+    /// replaced.
+    ///
+    /// `reader` must be seekable because `ArchiveFormat::Zip` requires random access to read the
+    /// central directory at the end of the stream; `ArchiveFormat::Tar` doesn't need it, but takes
+    /// the same bound so both formats can be imported through one method.
+    ///
+    /// # Errors
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn import_archive(&mut self, reader: impl Read + io::Seek, format: ArchiveFormat) -> Result<()> {
+        match format {
+            ArchiveFormat::Tar => self.import_tar(reader),
+            ArchiveFormat::Zip => self.import_zip(reader),
+        }
+    }
+
+    /// Export the entries in this archive to `writer` in the given `format`.
+    ///
+    /// Entries are written in order of their path so that directories precede their descendants.
+    ///
+    /// `writer` must be seekable because `ArchiveFormat::Zip` requires seeking back to patch in
+    /// local file header fields once each entry's compressed size is known; `ArchiveFormat::Tar`
+    /// doesn't need it, but takes the same bound so both formats can be exported through one
+    /// method.
+    ///
+    /// # Errors
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn export_archive(&mut self, writer: impl Write + io::Seek, format: ArchiveFormat) -> Result<()> {
+        match format {
+            ArchiveFormat::Tar => self.export_tar(writer),
+            ArchiveFormat::Zip => self.export_zip(writer),
+        }
+    }
+
+    fn import_tar(&mut self, reader: impl Read) -> Result<()> {
+        let mut tar = tar::Archive::new(reader);
+
+        for entry_result in tar.entries()? {
+            let mut tar_entry = entry_result?;
+            let header = tar_entry.header().clone();
+
+            let path = tar_entry.path()?.into_owned();
+            let dest = match RelativePath::from_path(&path) {
+                Ok(path) => path.to_relative_path_buf(),
+                Err(_) => continue,
+            };
+
+            let modified_time = UNIX_EPOCH + Duration::from_secs(header.mtime().unwrap_or(0));
+            let permissions = header.mode().ok().map(|mode| mode as i32);
+
+            let mut attributes = Vec::new();
+            if let Some(extensions) = tar_entry.pax_extensions()? {
+                for extension in extensions {
+                    let extension = extension?;
+                    if let Some(name) = extension.key().ok().and_then(|key| key.strip_prefix(XATTR_PAX_PREFIX)) {
+                        attributes.push(ExtendedAttribute {
+                            name: name.to_owned(),
+                            value: extension.value_bytes().to_vec(),
+                        });
+                    }
+                }
+            }
+
+            let entry_type = match header.entry_type() {
+                TarEntryType::Directory => EntryType::Directory,
+                TarEntryType::Symlink => EntryType::Link {
+                    target: tar_entry
+                        .link_name()?
+                        .ok_or_else(|| {
+                            io::Error::new(ErrorKind::InvalidData, "Symlink entry has no target.")
+                        })?
+                        .into_owned(),
+                },
+                _ => {
+                    let handle = self.write(&mut tar_entry)?;
+                    EntryType::File { data: handle }
+                }
+            };
+
+            let entry = ArchiveEntry {
+                modified_time,
+                permissions,
+                uid: header.uid().ok().map(|uid| uid as u32),
+                gid: header.gid().ok().map(|gid| gid as u32),
+                attributes,
+                entry_type,
+            };
+
+            self.insert(dest.as_relative_path(), entry);
+        }
+
+        Ok(())
+    }
+
+    fn export_tar(&mut self, writer: impl Write) -> Result<()> {
+        let mut builder = TarBuilder::new(writer);
+
+        let mut paths = self
+            .walk(RelativePath::new(""))
+            .into_iter()
+            .map(|path| path.to_relative_path_buf())
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        for path in paths {
+            let entry = self.entry(path.as_relative_path()).expect("Entry disappeared during export.");
+
+            // Emit a PAX extended header ahead of the main entry carrying the entry's extended
+            // attributes, which a ustar header has no field for.
+            if !entry.attributes.is_empty() {
+                let mut pax_data = Vec::new();
+                for attribute in &entry.attributes {
+                    pax_data.extend(pax_record(
+                        &format!("{}{}", XATTR_PAX_PREFIX, attribute.name),
+                        &attribute.value,
+                    ));
+                }
+
+                let mut pax_header = TarHeader::new_ustar();
+                pax_header.set_entry_type(TarEntryType::XHeader);
+                pax_header.set_size(pax_data.len() as u64);
+                pax_header.set_cksum();
+                builder.append_data(
+                    &mut pax_header,
+                    format!("./PaxHeaders/{}", path.as_str()),
+                    pax_data.as_slice(),
+                )?;
+            }
+
+            let mut header = TarHeader::new_ustar();
+            header.set_mtime(
+                entry
+                    .modified_time
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs(),
+            );
+            if let Some(mode) = entry.permissions {
+                header.set_mode(mode as u32);
+            }
+            if let Some(uid) = entry.uid {
+                header.set_uid(uid as u64);
+            }
+            if let Some(gid) = entry.gid {
+                header.set_gid(gid as u64);
+            }
+
+            match entry.entry_type {
+                EntryType::File { data } => {
+                    let mut reader = self.read(&data)?;
+                    header.set_size(data.size());
+                    header.set_entry_type(TarEntryType::Regular);
+                    builder.append_data(&mut header, path.as_str(), &mut reader)?;
+                }
+                EntryType::Directory => {
+                    header.set_size(0);
+                    header.set_entry_type(TarEntryType::Directory);
+                    builder.append_data(&mut header, format!("{}/", path.as_str()), io::empty())?;
+                }
+                EntryType::Link { target } => {
+                    header.set_size(0);
+                    header.set_entry_type(TarEntryType::Symlink);
+                    builder.append_link(&mut header, path.as_str(), &target)?;
+                }
+            }
+        }
+
+        builder.into_inner()?;
+
+        Ok(())
+    }
+
+    fn import_zip(&mut self, reader: impl Read + io::Seek) -> Result<()> {
+        let mut zip = ZipArchive::new(reader)?;
+
+        for index in 0..zip.len() {
+            let mut zip_entry = zip.by_index(index)?;
+
+            let dest = match RelativePath::from_path(zip_entry.name()) {
+                Ok(path) => path.to_relative_path_buf(),
+                Err(_) => continue,
+            };
+
+            let modified_time = zip_entry
+                .last_modified()
+                .to_time()
+                .map(|time| UNIX_EPOCH + Duration::from_secs(time.unix_timestamp().max(0) as u64))
+                .unwrap_or(UNIX_EPOCH);
+            let permissions = zip_entry.unix_mode().map(|mode| mode as i32);
+
+            let entry_type = if zip_entry.is_dir() {
+                EntryType::Directory
+            } else if permissions.map_or(false, |mode| (mode as u32) & 0o170000 == 0o120000) {
+                let mut target = String::new();
+                zip_entry.read_to_string(&mut target)?;
+                EntryType::Link { target: target.into() }
+            } else {
+                let handle = self.write(&mut zip_entry)?;
+                EntryType::File { data: handle }
+            };
+
+            let entry = ArchiveEntry {
+                modified_time,
+                permissions,
+                uid: None,
+                gid: None,
+                attributes: Vec::new(),
+                entry_type,
+            };
+
+            self.insert(dest.as_relative_path(), entry);
+        }
+
+        Ok(())
+    }
+
+    fn export_zip(&mut self, writer: impl Write + io::Seek) -> Result<()> {
+        let mut zip = ZipWriter::new(writer);
+
+        let mut paths = self
+            .walk(RelativePath::new(""))
+            .into_iter()
+            .map(|path| path.to_relative_path_buf())
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        for path in paths {
+            let entry = self.entry(path.as_relative_path()).expect("Entry disappeared during export.");
+
+            let mut options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+            if let Some(mode) = entry.permissions {
+                options = options.unix_permissions(mode as u32);
+            }
+
+            match entry.entry_type {
+                EntryType::File { data } => {
+                    zip.start_file(path.as_str(), options)?;
+                    copy(&mut self.read(&data)?, &mut zip)?;
+                }
+                EntryType::Directory => {
+                    zip.add_directory(path.as_str(), options)?;
+                }
+                EntryType::Link { target } => {
+                    // Zip has no native symlink support; store the target path as the entry's
+                    // contents and mark it with the symlink bit in the Unix mode, matching the
+                    // convention used by `zip`/`unzip` and Info-ZIP.
+                    let options = options.unix_permissions(0o120000 | (entry.permissions.unwrap_or(0o777) as u32));
+                    zip.start_file(path.as_str(), options)?;
+                    zip.write_all(target.to_string_lossy().as_bytes())?;
+                }
+            }
+        }
+
+        zip.finish()?;
+
+        Ok(())
+    }
 }
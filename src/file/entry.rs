@@ -0,0 +1,53 @@
+/*
+ * Copyright 2019 Garrett Powell
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::EntryType;
+
+/// An extended attribute of a file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExtendedAttribute {
+    /// The name of the attribute.
+    pub name: String,
+
+    /// The value of the attribute.
+    pub value: Vec<u8>,
+}
+
+/// Metadata about a file which is stored in a `FileArchive`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveEntry {
+    /// The time the file was last modified.
+    pub modified_time: SystemTime,
+
+    /// The POSIX permissions bits of the file, or `None` if POSIX permissions are not applicable.
+    pub permissions: Option<i32>,
+
+    /// The ID of the user which owns the file, or `None` if it was not recorded.
+    pub uid: Option<u32>,
+
+    /// The ID of the group which owns the file, or `None` if it was not recorded.
+    pub gid: Option<u32>,
+
+    /// The file's extended attributes.
+    pub attributes: Vec<ExtendedAttribute>,
+
+    /// The type of file this entry represents.
+    pub entry_type: EntryType,
+}
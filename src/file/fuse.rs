@@ -0,0 +1,326 @@
+/*
+ * Copyright 2019 Garrett Powell
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! A read-only FUSE filesystem backed by a `FileArchive`.
+//!
+//! This lets callers browse a large archive with ordinary tools (`ls`, `cat`, `grep`) without
+//! extracting it to scratch space first. Each entry is read lazily through `Archive::read` when
+//! its contents are actually requested.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::io::Read;
+use std::time::{Duration, UNIX_EPOCH};
+
+use fuser::{
+    FileAttr, FileType, Filesystem, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry, Request,
+};
+use relative_path::{RelativePath, RelativePathBuf};
+
+use crate::error::Result;
+use crate::EntryType;
+
+use super::archive::FileArchive;
+use super::entry::ArchiveEntry;
+
+/// How long the kernel is allowed to cache attributes and directory entries.
+///
+/// Because this filesystem is read-only and backed by an archive that isn't expected to change
+/// out from under the mount, we can cache aggressively.
+const ATTR_TTL: Duration = Duration::from_secs(60);
+
+/// The inode number of the archive's root directory.
+const ROOT_INODE: u64 = 1;
+
+/// A read-only FUSE filesystem which exposes the entries of a `FileArchive`.
+///
+/// Inodes are assigned to archive paths the first time they're looked up, starting from
+/// `ROOT_INODE` for the archive root.
+pub struct ArchiveFilesystem {
+    archive: FileArchive,
+    paths_by_inode: HashMap<u64, RelativePathBuf>,
+    inodes_by_path: HashMap<RelativePathBuf, u64>,
+    next_inode: u64,
+}
+
+impl ArchiveFilesystem {
+    /// Create a new `ArchiveFilesystem` backed by `archive`.
+    pub fn new(archive: FileArchive) -> Self {
+        let mut paths_by_inode = HashMap::new();
+        paths_by_inode.insert(ROOT_INODE, RelativePathBuf::new());
+        let mut inodes_by_path = HashMap::new();
+        inodes_by_path.insert(RelativePathBuf::new(), ROOT_INODE);
+
+        Self {
+            archive,
+            paths_by_inode,
+            inodes_by_path,
+            next_inode: ROOT_INODE + 1,
+        }
+    }
+
+    /// Look up the inode assigned to `path`, assigning it a new one if necessary.
+    fn inode_for(&mut self, path: &RelativePath) -> u64 {
+        if let Some(inode) = self.inodes_by_path.get(path) {
+            return *inode;
+        }
+
+        let inode = self.next_inode;
+        self.next_inode += 1;
+        self.paths_by_inode.insert(inode, path.to_relative_path_buf());
+        self.inodes_by_path.insert(path.to_relative_path_buf(), inode);
+        inode
+    }
+
+    /// Return the `FileAttr` for the entry at `path` with the given `inode`.
+    fn attr_for(&self, inode: u64, path: &RelativePath) -> Option<FileAttr> {
+        if path.as_str().is_empty() {
+            return Some(directory_attr(inode, 0));
+        }
+
+        let entry = self.archive.entry(path)?;
+        Some(entry_attr(inode, &entry))
+    }
+}
+
+/// Build the `FileAttr` for the archive root or any other directory.
+fn directory_attr(inode: u64, size: u64) -> FileAttr {
+    FileAttr {
+        ino: inode,
+        size,
+        blocks: 0,
+        atime: UNIX_EPOCH,
+        mtime: UNIX_EPOCH,
+        ctime: UNIX_EPOCH,
+        crtime: UNIX_EPOCH,
+        kind: FileType::Directory,
+        perm: 0o555,
+        nlink: 2,
+        uid: unsafe { libc::getuid() },
+        gid: unsafe { libc::getgid() },
+        rdev: 0,
+        blksize: 512,
+        flags: 0,
+    }
+}
+
+/// Build the `FileAttr` for a regular file, directory, or symlink entry.
+fn entry_attr(inode: u64, entry: &ArchiveEntry) -> FileAttr {
+    let (kind, size, perm) = match &entry.entry_type {
+        EntryType::File { data } => (FileType::RegularFile, data.size(), 0o444),
+        EntryType::Directory => (FileType::Directory, 0, 0o555),
+        EntryType::Link { target } => (FileType::Symlink, target.as_os_str().len() as u64, 0o444),
+    };
+
+    let perm = entry
+        .permissions
+        .map(|mode| (mode as u32 & 0o777) as u16)
+        .unwrap_or(perm);
+
+    FileAttr {
+        ino: inode,
+        size,
+        blocks: (size + 511) / 512,
+        atime: entry.modified_time,
+        mtime: entry.modified_time,
+        ctime: entry.modified_time,
+        crtime: entry.modified_time,
+        kind,
+        perm,
+        nlink: 1,
+        uid: entry.uid.unwrap_or_else(|| unsafe { libc::getuid() }),
+        gid: entry.gid.unwrap_or_else(|| unsafe { libc::getgid() }),
+        rdev: 0,
+        blksize: 512,
+        flags: 0,
+    }
+}
+
+impl Filesystem for ArchiveFilesystem {
+    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let parent_path = match self.paths_by_inode.get(&parent) {
+            Some(path) => path.clone(),
+            None => return reply.error(libc::ENOENT),
+        };
+
+        let name = match name.to_str() {
+            Some(name) => name,
+            None => return reply.error(libc::ENOENT),
+        };
+        let child_path = parent_path.join(name);
+
+        if self.archive.entry(&child_path).is_none() {
+            return reply.error(libc::ENOENT);
+        }
+
+        let inode = self.inode_for(&child_path);
+        match self.attr_for(inode, &child_path) {
+            Some(attr) => reply.entry(&ATTR_TTL, &attr, 0),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
+        let path = match self.paths_by_inode.get(&ino) {
+            Some(path) => path.clone(),
+            None => return reply.error(libc::ENOENT),
+        };
+
+        match self.attr_for(ino, &path) {
+            Some(attr) => reply.attr(&ATTR_TTL, &attr),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn readlink(&mut self, _req: &Request, ino: u64, reply: ReplyData) {
+        let path = match self.paths_by_inode.get(&ino) {
+            Some(path) => path.clone(),
+            None => return reply.error(libc::ENOENT),
+        };
+
+        match self.archive.entry(&path) {
+            Some(ArchiveEntry {
+                entry_type: EntryType::Link { target },
+                ..
+            }) => reply.data(target.to_string_lossy().as_bytes()),
+            _ => reply.error(libc::EINVAL),
+        }
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        let path = match self.paths_by_inode.get(&ino) {
+            Some(path) => path.clone(),
+            None => return reply.error(libc::ENOENT),
+        };
+
+        let entry = match self.archive.entry(&path) {
+            Some(entry) => entry,
+            None => return reply.error(libc::ENOENT),
+        };
+
+        let data = match entry.entry_type {
+            EntryType::File { data } => data,
+            _ => return reply.error(libc::EISDIR),
+        };
+
+        match read_range(&self.archive, &data, offset as u64, size as usize) {
+            Ok(bytes) => reply.data(&bytes),
+            Err(_) => reply.error(libc::EIO),
+        }
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        let path = match self.paths_by_inode.get(&ino) {
+            Some(path) => path.clone(),
+            None => return reply.error(libc::ENOENT),
+        };
+
+        let mut children = self
+            .archive
+            .list(&path)
+            .into_iter()
+            .map(|child| child.to_relative_path_buf())
+            .collect::<Vec<_>>();
+        children.sort();
+
+        let mut entries = vec![
+            (ino, FileType::Directory, ".".to_string()),
+            (ino, FileType::Directory, "..".to_string()),
+        ];
+        for child in children {
+            let name = child
+                .file_name()
+                .map(|name| name.to_string())
+                .unwrap_or_default();
+            let kind = match self.archive.entry(&child) {
+                Some(ArchiveEntry {
+                    entry_type: EntryType::Directory,
+                    ..
+                }) => FileType::Directory,
+                Some(ArchiveEntry {
+                    entry_type: EntryType::Link { .. },
+                    ..
+                }) => FileType::Symlink,
+                _ => FileType::RegularFile,
+            };
+            let inode = self.inode_for(&child);
+            entries.push((inode, kind, name));
+        }
+
+        for (index, (inode, kind, name)) in entries.into_iter().enumerate().skip(offset as usize) {
+            if reply.add(inode, (index + 1) as i64, kind, name) {
+                break;
+            }
+        }
+
+        reply.ok();
+    }
+}
+
+/// Read `size` bytes starting at `offset` from the data referenced by `handle`.
+///
+/// `Archive::read` only supports sequential reads from the start, so for now this re-opens the
+/// reader and discards the bytes before `offset`. This is wasteful for large files read
+/// out-of-order, but correct, and leaves room to switch to a seekable reader later without
+/// changing this filesystem's interface.
+fn read_range(
+    archive: &FileArchive,
+    handle: &crate::DataHandle,
+    offset: u64,
+    size: usize,
+) -> Result<Vec<u8>> {
+    let mut reader = archive.read(handle)?;
+    io_skip(&mut reader, offset)?;
+
+    let mut buffer = vec![0u8; size];
+    let mut total_read = 0;
+    loop {
+        let bytes_read = reader.read(&mut buffer[total_read..])?;
+        if bytes_read == 0 {
+            break;
+        }
+        total_read += bytes_read;
+        if total_read == buffer.len() {
+            break;
+        }
+    }
+    buffer.truncate(total_read);
+
+    Ok(buffer)
+}
+
+/// Discard `amount` bytes from the front of `reader`.
+fn io_skip(reader: &mut impl Read, amount: u64) -> std::io::Result<()> {
+    std::io::copy(&mut reader.by_ref().take(amount), &mut std::io::sink())?;
+    Ok(())
+}
@@ -0,0 +1,71 @@
+/*
+ * Copyright 2019 Garrett Powell
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Platform-specific helpers for reading and applying file metadata.
+//!
+//! These wrap the handful of Unix-only operations `FileArchive` needs when archiving or
+//! extracting a real file: POSIX permission bits, extended attributes, and symlinks.
+
+use std::fs::Metadata;
+use std::io;
+use std::os::unix::fs::{symlink, MetadataExt};
+use std::path::Path;
+
+use xattr::{list, get, set};
+
+use super::entry::ExtendedAttribute;
+
+/// Return the POSIX permissions bits of `metadata`.
+pub fn file_mode(metadata: &Metadata) -> Option<i32> {
+    Some((metadata.mode() & 0o7777) as i32)
+}
+
+/// Apply the permissions bits `mode` to the file at `path`.
+pub fn set_file_mode(path: &Path, mode: i32) -> io::Result<()> {
+    use std::fs::{set_permissions, Permissions};
+    use std::os::unix::fs::PermissionsExt;
+
+    set_permissions(path, Permissions::from_mode(mode as u32))
+}
+
+/// Read all extended attributes of the file at `path`.
+pub fn extended_attrs(path: &Path) -> io::Result<Vec<ExtendedAttribute>> {
+    let mut attributes = Vec::new();
+
+    for name in list(path)? {
+        let value = get(path, &name)?.unwrap_or_default();
+        attributes.push(ExtendedAttribute {
+            name: name.to_string_lossy().into_owned(),
+            value,
+        });
+    }
+
+    Ok(attributes)
+}
+
+/// Apply `attributes` to the file at `path`.
+pub fn set_extended_attrs(path: &Path, attributes: Vec<ExtendedAttribute>) -> io::Result<()> {
+    for attribute in attributes {
+        set(path, &attribute.name, &attribute.value)?;
+    }
+
+    Ok(())
+}
+
+/// Create a symbolic link at `dest` pointing to `target`.
+pub fn soft_link(dest: &Path, target: &Path) -> io::Result<()> {
+    symlink(target, dest)
+}
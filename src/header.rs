@@ -14,26 +14,164 @@
  * limitations under the License.
  */
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::mem::size_of;
 use std::path::{Path, PathBuf};
 
 use chrono::NaiveDateTime;
 use rmp_serde::{decode, encode};
 use serde::{Deserialize, Serialize};
+use tar::{Builder as TarBuilder, EntryType as TarEntryType, Header as TarHeader};
 
 use crate::block::{BLOCK_OFFSET, BlockAddress, pad_to_block_size};
+use crate::chunking::{Chunker, ChunkerConfig};
 use crate::error::Result;
 use crate::serialization::SerializableNaiveDateTime;
 
+/// The PAX extended header key prefix used to store an extended attribute, following the
+/// `SCHILY.xattr.<name>` convention used by GNU tar and libarchive.
+const XATTR_PAX_PREFIX: &str = "SCHILY.xattr.";
+
+/// Format one extended attribute as a PAX extended header record.
+///
+/// A PAX record has the form `"<length> <key>=<value>\n"`, where `<length>` is the length of the
+/// whole record, including itself. Because the length of the length prefix can itself affect the
+/// total length, we have to solve for it iteratively.
+fn pax_record(key: &str, value: &[u8]) -> Vec<u8> {
+    let suffix_len = key.len() + value.len() + 3; // b' ', b'=', and b'\n'
+    let mut total_len = suffix_len + 1;
+    loop {
+        let digits = total_len.to_string().len();
+        let candidate = digits + suffix_len;
+        if candidate.to_string().len() == digits {
+            total_len = candidate;
+            break;
+        }
+        total_len = candidate;
+    }
+
+    let mut record = format!("{} {}=", total_len, key).into_bytes();
+    record.extend_from_slice(value);
+    record.push(b'\n');
+    record
+}
+
 /// The size of the checksum of each file.
 pub const FILE_HASH_SIZE: usize = 32;
 
 /// The checksum of a file.
 pub type FileChecksum = [u8; FILE_HASH_SIZE];
 
+/// The default size, in bytes, of the integrity blocks a file's contents are divided into.
+pub const DEFAULT_INTEGRITY_BLOCK_SIZE: u64 = 4096;
+
+/// The deduplicated, on-disk representation of one chunk of file data.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Chunk {
+    /// The locations of the blocks containing this chunk's data.
+    pub blocks: Vec<BlockAddress>,
+}
+
+/// Options controlling which recorded metadata is restored when an archive's entries are written
+/// out as a tar stream.
+///
+/// These mirror the `preserve_permissions`/`preserve_ownerships`/`preserve_mtime`/`unpack_xattrs`
+/// knobs the `tar` crate itself exposes for unpacking, letting unprivileged restores skip
+/// ownership, CI restores normalize timestamps, and security-sensitive restores drop extended
+/// attributes, all without discarding the metadata from the stored `Header`.
+#[derive(Debug, Clone, Copy)]
+pub struct RestoreOptions {
+    /// Apply the entry's recorded POSIX permissions bits, masked by `mask`.
+    pub preserve_permissions: bool,
+
+    /// Apply the entry's recorded modification time.
+    pub preserve_mtime: bool,
+
+    /// Apply the entry's recorded extended attributes.
+    pub unpack_xattrs: bool,
+
+    /// Apply the entry's recorded `uid` and `gid`.
+    ///
+    /// Changing ownership on extraction typically requires the process to have elevated
+    /// privileges, which is why this defaults to `false`.
+    pub preserve_ownerships: bool,
+
+    /// A mask applied to the entry's permissions bits before they are written into the tar
+    /// header.
+    pub mask: i32,
+}
+
+impl Default for RestoreOptions {
+    fn default() -> Self {
+        Self {
+            preserve_permissions: true,
+            preserve_mtime: true,
+            unpack_xattrs: true,
+            preserve_ownerships: false,
+            mask: !0,
+        }
+    }
+}
+
+/// A byte range within a file's contents that failed an integrity check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CorruptRange {
+    /// The offset of this range from the start of the file.
+    pub offset: u64,
+
+    /// The length of this range in bytes.
+    pub length: u64,
+}
+
+/// A problem found by [`Header::scrub`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScrubIssue {
+    /// An entry's recomputed whole-file checksum didn't match the one stored in the header.
+    ChecksumMismatch {
+        /// The path of the affected entry.
+        path: PathBuf,
+    },
+
+    /// An entry references a chunk hash that isn't in [`Header::chunks`].
+    DanglingChunk {
+        /// The path of the affected entry.
+        path: PathBuf,
+
+        /// The missing chunk hash.
+        hash: [u8; 32],
+    },
+
+    /// A chunk's blocks fall outside the range of valid blocks for this archive.
+    OutOfRangeBlocks {
+        /// The hash of the affected chunk.
+        hash: [u8; 32],
+    },
+
+    /// Two chunks claim the exact same block.
+    OverlappingBlocks {
+        /// The hash of the chunk which first claimed the block.
+        first: [u8; 32],
+
+        /// The hash of the chunk which claims the same block.
+        second: [u8; 32],
+    },
+
+    /// A chunk has a reference count of zero but was not reclaimed.
+    OrphanedChunk {
+        /// The hash of the orphaned chunk.
+        hash: [u8; 32],
+    },
+}
+
+/// The result of a [`Header::scrub`] pass.
+#[derive(Debug, Clone, Default)]
+pub struct ScrubReport {
+    /// The problems found, in the order they were discovered.
+    pub issues: Vec<ScrubIssue>,
+}
+
 /// A type of file which can be stored in an archive.
 #[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum EntryType {
@@ -42,11 +180,19 @@ pub enum EntryType {
         /// The size of the file in bytes.
         size: u64,
 
-        /// The BLAKE2 checksum of the file.
+        /// The BLAKE2 checksum of the whole file.
         checksum: FileChecksum,
 
-        /// The locations of blocks containing the data for this file.
-        blocks: Vec<BlockAddress>,
+        /// The size, in bytes, of each integrity block this file's contents are divided into, for
+        /// the purpose of locating corruption. The last block may be shorter.
+        block_size: u64,
+
+        /// The BLAKE2 checksum of each integrity block, in order.
+        block_hashes: Vec<FileChecksum>,
+
+        /// The hashes of the deduplicated chunks making up this file's contents, in order. Each
+        /// hash is a key into [`Header::chunks`].
+        chunks: Vec<[u8; 32]>,
     },
 
     /// A directory.
@@ -82,6 +228,12 @@ pub struct ArchiveEntry {
     /// The POSIX permissions bits of the file, or `None` if POSIX permissions are not applicable.
     pub permissions: Option<i32>,
 
+    /// The ID of the user that owns the file, or `None` if this is not recorded.
+    pub uid: Option<u32>,
+
+    /// The ID of the group that owns the file, or `None` if this is not recorded.
+    pub gid: Option<u32>,
+
     /// The file's extended attributes.
     pub attributes: Vec<ExtendedAttribute>,
 
@@ -94,22 +246,165 @@ pub struct ArchiveEntry {
 pub struct Header {
     /// The entries which are stored in this archive.
     pub entries: Vec<ArchiveEntry>,
+
+    /// A global table of deduplicated chunks, keyed by BLAKE2 hash, paired with the number of
+    /// references to each chunk from entries' chunk lists.
+    pub chunks: HashMap<[u8; 32], (Chunk, u64)>,
 }
 
 impl Header {
     /// Returns the set of locations of blocks used for storing data.
+    ///
+    /// A chunk whose reference count has dropped to zero is no longer considered live, even
+    /// though it may still be present in [`Header::chunks`] until [`Header::scrub_and_repair`]
+    /// reclaims it.
     fn data_blocks(&self) -> Vec<BlockAddress> {
-        self.entries
-            .iter()
-            .filter_map(|entry| match &entry.entry_type {
-                EntryType::File { blocks, .. } => Some(blocks),
-                _ => None
-            })
-            .flatten()
+        self.chunks
+            .values()
+            .filter(|(_, refcount)| *refcount > 0)
+            .flat_map(|(chunk, _)| &chunk.blocks)
             .copied()
             .collect()
     }
 
+    /// Removes the entry at `path`, if any, decrementing the reference count of any chunks it
+    /// referenced and removing chunks whose count reaches zero.
+    ///
+    /// Returns the removed entry, or `None` if no entry exists at `path`.
+    pub fn remove_entry(&mut self, path: &Path) -> Option<ArchiveEntry> {
+        let index = self.entries.iter().position(|entry| entry.path == path)?;
+        let entry = self.entries.remove(index);
+
+        if let EntryType::File { chunks, .. } = &entry.entry_type {
+            for hash in chunks {
+                if let Some((_, refcount)) = self.chunks.get_mut(hash) {
+                    *refcount -= 1;
+                    if *refcount == 0 {
+                        self.chunks.remove(hash);
+                    }
+                }
+            }
+        }
+
+        Some(entry)
+    }
+
+    /// Recomputes integrity block hashes for every entry and returns the corrupt ranges found,
+    /// paired with the path of the entry they belong to.
+    ///
+    /// # Errors
+    /// - `Error::Io`: An I/O error occurred reading from `archive`.
+    /// - `Error::Corrupt`: An entry references a chunk hash that isn't in `self.chunks`.
+    pub fn verify(&self, archive: &Path) -> Result<Vec<(&Path, CorruptRange)>> {
+        let mut corrupt_ranges = Vec::new();
+        for entry in &self.entries {
+            for range in entry.verify(archive, &self.chunks)? {
+                corrupt_ranges.push((entry.path.as_path(), range));
+            }
+        }
+        Ok(corrupt_ranges)
+    }
+
+    /// Walks every entry, recomputing whole-file checksums and cross-checking chunks against
+    /// `location`'s valid block range, without making any changes.
+    ///
+    /// This generalizes the self-healing that `SuperBlock::read` does for the header itself to
+    /// the data layer: it validates file content against its known-good hashes before the caller
+    /// trusts it, and flags blocks that are out of range, overlapping, or orphaned.
+    ///
+    /// Only exact, identical block claims are reported as overlaps; this doesn't detect two
+    /// chunks whose byte ranges partially overlap without being identical.
+    ///
+    /// # Errors
+    /// - `Error::Io`: An I/O error occurred reading from `archive`.
+    pub fn scrub(&self, archive: &Path, location: &HeaderAddress) -> Result<ScrubReport> {
+        let mut report = ScrubReport::default();
+        let valid_blocks: HashSet<BlockAddress> = location.blocks().into_iter().collect();
+        let mut block_owners: HashMap<BlockAddress, [u8; 32]> = HashMap::new();
+
+        for (hash, (chunk, refcount)) in &self.chunks {
+            if *refcount == 0 {
+                report.issues.push(ScrubIssue::OrphanedChunk { hash: *hash });
+                continue;
+            }
+
+            if chunk.blocks.iter().any(|block| !valid_blocks.contains(block)) {
+                report.issues.push(ScrubIssue::OutOfRangeBlocks { hash: *hash });
+            }
+
+            for block in &chunk.blocks {
+                if let Some(owner) = block_owners.insert(*block, *hash) {
+                    if owner != *hash {
+                        report
+                            .issues
+                            .push(ScrubIssue::OverlappingBlocks { first: owner, second: *hash });
+                    }
+                }
+            }
+        }
+
+        let mut archive_file = File::open(archive)?;
+        for entry in &self.entries {
+            let (size, checksum, chunks) = match &entry.entry_type {
+                EntryType::File { size, checksum, chunks, .. } => (*size, *checksum, chunks),
+                _ => continue,
+            };
+
+            let missing_chunk = chunks.iter().any(|hash| !self.chunks.contains_key(hash));
+            if missing_chunk {
+                for hash in chunks.iter().filter(|hash| !self.chunks.contains_key(*hash)) {
+                    report
+                        .issues
+                        .push(ScrubIssue::DanglingChunk { path: entry.path.clone(), hash: *hash });
+                }
+                continue;
+            }
+
+            let contents = read_file_contents(&mut archive_file, chunks, &self.chunks)?;
+            if contents.len() as u64 != size || blake2_checksum(&contents) != checksum {
+                report
+                    .issues
+                    .push(ScrubIssue::ChecksumMismatch { path: entry.path.clone() });
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Runs `scrub`, then repairs what it safely can: entries with a checksum mismatch or a
+    /// dangling chunk reference are dropped from the header, and chunks found to be orphaned are
+    /// reclaimed, complementing `unused_blocks`.
+    ///
+    /// Returns the report describing what was found, before repair.
+    ///
+    /// # Errors
+    /// - `Error::Io`: An I/O error occurred reading from `archive`.
+    pub fn scrub_and_repair(&mut self, archive: &Path, location: &HeaderAddress) -> Result<ScrubReport> {
+        let report = self.scrub(archive, location)?;
+
+        let irrecoverable_paths: HashSet<&Path> = report
+            .issues
+            .iter()
+            .filter_map(|issue| match issue {
+                ScrubIssue::ChecksumMismatch { path } | ScrubIssue::DanglingChunk { path, .. } => {
+                    Some(path.as_path())
+                }
+                _ => None,
+            })
+            .collect();
+        for path in irrecoverable_paths {
+            self.remove_entry(path);
+        }
+
+        for issue in &report.issues {
+            if let ScrubIssue::OrphanedChunk { hash } = issue {
+                self.chunks.remove(hash);
+            }
+        }
+
+        Ok(report)
+    }
+
     /// Returns a list of addresses of blocks which are unused and can be overwritten.
     pub fn unused_blocks(&self, location: &HeaderAddress) -> Vec<BlockAddress> {
         let mut used_blocks = HashSet::new();
@@ -178,6 +473,324 @@ impl Header {
 
         Ok(HeaderAddress { offset, header_size, archive_size })
     }
+
+    /// Writes the entries in this header to `writer` as a PAX-format tar archive, reading file
+    /// contents from `archive`.
+    ///
+    /// This uses the default `RestoreOptions`, which preserves everything that was recorded
+    /// except ownership. To customize this behavior, use `to_tar_with`.
+    ///
+    /// # Errors
+    /// - `Error::Io`: An I/O error occurred reading from `archive` or writing to `writer`.
+    pub fn to_tar<W: Write>(&self, archive: &Path, writer: W) -> Result<()> {
+        self.to_tar_with(archive, writer, &RestoreOptions::default())
+    }
+
+    /// Writes the entries in this header to `writer` as a PAX-format tar archive, reading file
+    /// contents from `archive`, applying the given `options` to decide which recorded metadata is
+    /// written into the tar headers.
+    ///
+    /// Extended attributes are written as `SCHILY.xattr.<name>` PAX extended header records, the
+    /// same convention used by GNU tar and libarchive.
+    ///
+    /// # Errors
+    /// - `Error::Io`: An I/O error occurred reading from `archive` or writing to `writer`.
+    pub fn to_tar_with<W: Write>(
+        &self,
+        archive: &Path,
+        writer: W,
+        options: &RestoreOptions,
+    ) -> Result<()> {
+        let mut archive_file = File::open(archive)?;
+        let mut builder = TarBuilder::new(writer);
+
+        for entry in &self.entries {
+            if options.unpack_xattrs && !entry.attributes.is_empty() {
+                let mut pax_data = Vec::new();
+                for attribute in &entry.attributes {
+                    let key = format!("{}{}", XATTR_PAX_PREFIX, attribute.name);
+                    pax_data.extend(pax_record(&key, &attribute.value));
+                }
+
+                let mut pax_header = TarHeader::new_ustar();
+                pax_header.set_entry_type(TarEntryType::XHeader);
+                pax_header.set_size(pax_data.len() as u64);
+                pax_header.set_cksum();
+                builder.append(&pax_header, pax_data.as_slice())?;
+            }
+
+            let mut header = TarHeader::new_ustar();
+            if options.preserve_mtime {
+                header.set_mtime(entry.modified_time.and_utc().timestamp() as u64);
+            }
+            if options.preserve_permissions {
+                if let Some(permissions) = entry.permissions {
+                    header.set_mode(permissions as u32 & options.mask as u32);
+                }
+            }
+            if options.preserve_ownerships {
+                if let Some(uid) = entry.uid {
+                    header.set_uid(uid as u64);
+                }
+                if let Some(gid) = entry.gid {
+                    header.set_gid(gid as u64);
+                }
+            }
+
+            match &entry.entry_type {
+                EntryType::File { size, chunks, .. } => {
+                    header.set_entry_type(TarEntryType::Regular);
+                    header.set_size(*size);
+                    header.set_path(&entry.path)?;
+                    header.set_cksum();
+
+                    let contents = read_file_contents(&mut archive_file, chunks, &self.chunks)?;
+                    builder.append(&header, contents.as_slice())?;
+                }
+                EntryType::Directory => {
+                    header.set_entry_type(TarEntryType::Directory);
+                    header.set_size(0);
+                    header.set_path(&entry.path)?;
+                    header.set_cksum();
+                    builder.append(&header, io::empty())?;
+                }
+                EntryType::Link { target } => {
+                    header.set_entry_type(TarEntryType::Symlink);
+                    header.set_size(0);
+                    header.set_path(&entry.path)?;
+                    header.set_link_name(target)?;
+                    header.set_cksum();
+                    builder.append(&header, io::empty())?;
+                }
+            }
+        }
+
+        builder.finish()?;
+        Ok(())
+    }
+
+    /// Reads a PAX-format tar archive from `reader`, writing file contents into `archive` and
+    /// returning the resulting header.
+    ///
+    /// # Errors
+    /// - `Error::Io`: An I/O error occurred reading from `reader` or writing to `archive`.
+    pub fn from_tar<R: Read>(archive: &Path, reader: R) -> Result<Header> {
+        let mut archive_file = File::options().read(true).append(true).open(archive)?;
+        let mut tar_archive = tar::Archive::new(reader);
+
+        let mut entries = Vec::new();
+        let mut pending_attributes = Vec::new();
+        let mut chunk_table: HashMap<[u8; 32], (Chunk, u64)> = HashMap::new();
+
+        for tar_entry in tar_archive.entries()? {
+            let mut tar_entry = tar_entry?;
+            let header = tar_entry.header();
+
+            if header.entry_type() == TarEntryType::XHeader {
+                let mut pax_data = Vec::new();
+                tar_entry.read_to_end(&mut pax_data)?;
+                pending_attributes = parse_pax_attributes(&pax_data);
+                continue;
+            }
+
+            let path = tar_entry.path()?.into_owned();
+            let modified_time =
+                NaiveDateTime::from_timestamp_opt(header.mtime().unwrap_or(0) as i64, 0)
+                    .unwrap_or_default();
+            let permissions = header.mode().ok().map(|mode| mode as i32);
+            let uid = header.uid().ok().map(|uid| uid as u32);
+            let gid = header.gid().ok().map(|gid| gid as u32);
+            let attributes = std::mem::take(&mut pending_attributes);
+
+            let entry_type = match header.entry_type() {
+                TarEntryType::Directory => EntryType::Directory,
+                TarEntryType::Symlink => EntryType::Link {
+                    target: tar_entry
+                        .link_name()?
+                        .map(|name| name.into_owned())
+                        .unwrap_or_default(),
+                },
+                _ => {
+                    let size = header.size()?;
+                    let mut contents = Vec::with_capacity(size as usize);
+                    tar_entry.read_to_end(&mut contents)?;
+
+                    let checksum = blake2_checksum(&contents);
+                    let block_size = DEFAULT_INTEGRITY_BLOCK_SIZE;
+                    let block_hashes = contents
+                        .chunks(block_size as usize)
+                        .map(blake2_checksum)
+                        .collect();
+
+                    // Split on content-defined boundaries rather than fixed offsets, so that a
+                    // small edit only shifts the chunk it falls in rather than every chunk after
+                    // it, which is what lets unchanged parts of a file still dedup on re-archival.
+                    let mut chunks = Vec::new();
+                    let mut chunker = Chunker::new(ChunkerConfig::default());
+                    chunker.chunk(contents.as_slice(), |chunk_data, _block_id| {
+                        chunks.push(
+                            write_chunk(&mut archive_file, &mut chunk_table, chunk_data)
+                                .map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))?,
+                        );
+                        Ok(())
+                    })?;
+
+                    EntryType::File { size, checksum, block_size, block_hashes, chunks }
+                }
+            };
+
+            entries.push(ArchiveEntry {
+                path,
+                modified_time,
+                permissions,
+                uid,
+                gid,
+                attributes,
+                entry_type,
+            });
+        }
+
+        Ok(Header { entries, chunks: chunk_table })
+    }
+}
+
+/// Writes `data` as a new deduplicated chunk if its hash isn't already in `chunk_table`, or bumps
+/// the reference count of the existing chunk if it is. Returns the chunk's hash.
+fn write_chunk(
+    archive_file: &mut File,
+    chunk_table: &mut HashMap<[u8; 32], (Chunk, u64)>,
+    data: &[u8],
+) -> Result<[u8; 32]> {
+    let hash = blake2_checksum(data);
+
+    match chunk_table.get_mut(&hash) {
+        Some((_, refcount)) => *refcount += 1,
+        None => {
+            let offset = archive_file.seek(SeekFrom::End(0))?;
+            archive_file.write_all(data)?;
+            let blocks = BlockAddress::range(offset, data.len() as u64);
+            chunk_table.insert(hash, (Chunk { blocks }, 1));
+        }
+    }
+
+    Ok(hash)
+}
+
+/// Reads the contents of a file out of `archive_file`, given the hashes of the deduplicated
+/// `chunks` making it up and the `chunk_table` those hashes are looked up in.
+///
+/// # Errors
+/// - `Error::Corrupt`: A chunk hash isn't present in `chunk_table`.
+fn read_file_contents(
+    archive_file: &mut File,
+    chunks: &[[u8; 32]],
+    chunk_table: &HashMap<[u8; 32], (Chunk, u64)>,
+) -> Result<Vec<u8>> {
+    let mut contents = Vec::new();
+    for hash in chunks {
+        let (chunk, _) = chunk_table.get(hash).ok_or(crate::Error::Corrupt)?;
+        for block in &chunk.blocks {
+            let mut buffer = vec![0u8; block.length() as usize];
+            archive_file.seek(SeekFrom::Start(block.offset()))?;
+            archive_file.read_exact(&mut buffer)?;
+            contents.extend_from_slice(&buffer);
+        }
+    }
+    Ok(contents)
+}
+
+impl ArchiveEntry {
+    /// Recomputes this entry's integrity block hashes and returns the byte ranges, relative to
+    /// the start of the file, that failed verification.
+    ///
+    /// Returns an empty list for entries which aren't regular files.
+    ///
+    /// # Errors
+    /// - `Error::Io`: An I/O error occurred reading from `archive`.
+    /// - `Error::Corrupt`: This entry references a chunk hash that isn't in `chunk_table`.
+    pub fn verify(
+        &self,
+        archive: &Path,
+        chunk_table: &HashMap<[u8; 32], (Chunk, u64)>,
+    ) -> Result<Vec<CorruptRange>> {
+        let (size, block_size, block_hashes, chunks) = match &self.entry_type {
+            EntryType::File { size, block_size, block_hashes, chunks, .. } => {
+                (*size, *block_size, block_hashes, chunks)
+            }
+            _ => return Ok(Vec::new()),
+        };
+
+        let mut archive_file = File::open(archive)?;
+        let contents = read_file_contents(&mut archive_file, chunks, chunk_table)?;
+
+        let mut corrupt_ranges = Vec::new();
+        for (index, expected_hash) in block_hashes.iter().enumerate() {
+            let start = index as u64 * block_size;
+            let end = (start + block_size).min(size);
+            if start >= end {
+                break;
+            }
+            let actual_hash = blake2_checksum(&contents[start as usize..end as usize]);
+            if actual_hash != *expected_hash {
+                corrupt_ranges.push(CorruptRange { offset: start, length: end - start });
+            }
+        }
+
+        Ok(corrupt_ranges)
+    }
+}
+
+/// Parse the PAX extended attribute records out of `pax_data`, returning the ones using the
+/// `SCHILY.xattr.<name>` convention.
+fn parse_pax_attributes(pax_data: &[u8]) -> Vec<ExtendedAttribute> {
+    let mut attributes = Vec::new();
+    let mut remaining = pax_data;
+
+    while !remaining.is_empty() {
+        let space = match remaining.iter().position(|&byte| byte == b' ') {
+            Some(index) => index,
+            None => break,
+        };
+        let length: usize = match std::str::from_utf8(&remaining[..space])
+            .ok()
+            .and_then(|digits| digits.parse().ok())
+        {
+            Some(length) => length,
+            None => break,
+        };
+        if length == 0 || length > remaining.len() {
+            break;
+        }
+
+        let record = &remaining[space + 1..length - 1];
+        if let Some(equals) = record.iter().position(|&byte| byte == b'=') {
+            let key = String::from_utf8_lossy(&record[..equals]);
+            if let Some(name) = key.strip_prefix(XATTR_PAX_PREFIX) {
+                attributes.push(ExtendedAttribute {
+                    name: name.to_string(),
+                    value: record[equals + 1..].to_vec(),
+                });
+            }
+        }
+
+        remaining = &remaining[length..];
+    }
+
+    attributes
+}
+
+/// Compute the BLAKE2 checksum of a file's contents, matching the format stored in
+/// [`EntryType::File::checksum`].
+fn blake2_checksum(data: &[u8]) -> FileChecksum {
+    use blake2::{Blake2b, Digest};
+
+    let mut hasher = Blake2b::<blake2::digest::consts::U32>::new();
+    hasher.update(data);
+    let result = hasher.finalize();
+
+    let mut checksum = [0u8; FILE_HASH_SIZE];
+    checksum.copy_from_slice(&result);
+    checksum
 }
 
 /// The address of the header in the archive.
@@ -0,0 +1,54 @@
+/*
+ * Copyright 2019 Garrett Powell
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Integrity verification for `ObjectArchive`.
+
+use std::hash::Hash;
+use std::io;
+
+use crate::ObjectArchive;
+
+impl<K> ObjectArchive<K>
+where
+    K: Eq + Hash + Clone,
+{
+    /// Walk every object in this archive and check that its content can be read back in full.
+    ///
+    /// Returns the keys of any objects that are missing or whose content could not be read, so a
+    /// caller can decide whether to repair or re-fetch them. This should be run before trusting an
+    /// archive for a long-lived backup, since bit-rot in the underlying store otherwise goes
+    /// unnoticed until the corrupted object is actually read.
+    ///
+    /// # Errors
+    /// This never returns `Err`: an object that can't be read is reported in the returned list
+    /// instead.
+    pub fn verify(&self) -> io::Result<Vec<K>> {
+        let mut corrupt = Vec::new();
+
+        for key in self.keys() {
+            let object = match self.get(key) {
+                Some(object) => object,
+                None => continue,
+            };
+
+            if self.read_all(object).is_err() {
+                corrupt.push(key.clone());
+            }
+        }
+
+        Ok(corrupt)
+    }
+}
@@ -0,0 +1,316 @@
+/*
+ * Copyright 2019-2020 Wren Powell
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Reed-Solomon erasure coding over `GF(2^8)`.
+//!
+//! This gives a stripe of `k` data blocks `m` parity blocks, such that any `m` of the `k + m`
+//! blocks can be lost and the stripe still reconstructed. The encoding matrix is built from a
+//! Cauchy matrix, which (unlike a naively constructed Vandermonde matrix) guarantees that every
+//! square submatrix is invertible, so reconstruction works no matter which blocks were lost.
+
+use std::fmt;
+
+/// The primitive polynomial used to construct `GF(2^8)`, matching the one used for QR codes and
+/// several RAID6 implementations.
+const POLY: u16 = 0x11d;
+
+/// Arithmetic in `GF(2^8)`, implemented with precomputed log/antilog tables.
+struct Gf256 {
+    exp: [u8; 512],
+    log: [u8; 256],
+}
+
+impl Gf256 {
+    fn new() -> Self {
+        let mut exp = [0u8; 512];
+        let mut log = [0u8; 256];
+
+        let mut x: u16 = 1;
+        for i in 0..255 {
+            exp[i] = x as u8;
+            log[x as usize] = i as u8;
+            x <<= 1;
+            if x & 0x100 != 0 {
+                x ^= POLY;
+            }
+        }
+        for i in 255..512 {
+            exp[i] = exp[i - 255];
+        }
+
+        Self { exp, log }
+    }
+
+    fn mul(&self, a: u8, b: u8) -> u8 {
+        if a == 0 || b == 0 {
+            return 0;
+        }
+        self.exp[self.log[a as usize] as usize + self.log[b as usize] as usize]
+    }
+
+    fn div(&self, a: u8, b: u8) -> u8 {
+        debug_assert!(b != 0, "division by zero in GF(2^8)");
+        if a == 0 {
+            return 0;
+        }
+        let diff = self.log[a as usize] as i32 - self.log[b as usize] as i32;
+        let diff = if diff < 0 { diff + 255 } else { diff };
+        self.exp[diff as usize]
+    }
+
+    /// Invert a square matrix over `GF(2^8)` using Gauss-Jordan elimination.
+    ///
+    /// Panics if `matrix` is singular. The matrices this module builds are Cauchy submatrices,
+    /// which are never singular, so this is only reachable if the encoding matrix itself is
+    /// constructed incorrectly.
+    fn invert(&self, matrix: &[Vec<u8>]) -> Vec<Vec<u8>> {
+        let n = matrix.len();
+        let mut aug = vec![vec![0u8; 2 * n]; n];
+        for (row, source_row) in matrix.iter().enumerate() {
+            aug[row][..n].clone_from_slice(source_row);
+            aug[row][n + row] = 1;
+        }
+
+        for col in 0..n {
+            let pivot = (col..n)
+                .find(|&row| aug[row][col] != 0)
+                .expect("matrix is singular");
+            aug.swap(col, pivot);
+
+            let inverse = self.div(1, aug[col][col]);
+            for value in aug[col].iter_mut() {
+                *value = self.mul(*value, inverse);
+            }
+
+            for row in 0..n {
+                if row == col || aug[row][col] == 0 {
+                    continue;
+                }
+                let factor = aug[row][col];
+                for j in 0..2 * n {
+                    let scaled = self.mul(factor, aug[col][j]);
+                    aug[row][j] ^= scaled;
+                }
+            }
+        }
+
+        (0..n).map(|row| aug[row][n..].to_vec()).collect()
+    }
+}
+
+/// An error returned by [`RsCode::reconstruct`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RsError {
+    /// Fewer than `data_shards` shards were available to reconstruct from.
+    NotEnoughShards,
+
+    /// The provided shards were not all the same length.
+    MismatchedShardLength,
+}
+
+impl fmt::Display for RsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotEnoughShards => write!(f, "not enough shards to reconstruct the stripe"),
+            Self::MismatchedShardLength => write!(f, "shards are not all the same length"),
+        }
+    }
+}
+
+impl std::error::Error for RsError {}
+
+/// A systematic Reed-Solomon code over `GF(2^8)` with `data_shards` data shards and
+/// `parity_shards` parity shards.
+pub struct RsCode {
+    data_shards: usize,
+    parity_shards: usize,
+    gf: Gf256,
+    /// The `(data_shards + parity_shards) x data_shards` encoding matrix. The first `data_shards`
+    /// rows are the identity matrix, since the code is systematic; the remaining rows are the
+    /// coefficients used to compute each parity shard from the data shards.
+    matrix: Vec<Vec<u8>>,
+}
+
+impl RsCode {
+    /// Build a new code with `data_shards` data shards and `parity_shards` parity shards.
+    ///
+    /// # Panics
+    /// Panics if either `data_shards` or `parity_shards` is `0`.
+    pub fn new(data_shards: usize, parity_shards: usize) -> Self {
+        assert!(data_shards > 0, "data_shards must be greater than 0");
+        assert!(parity_shards > 0, "parity_shards must be greater than 0");
+
+        let gf = Gf256::new();
+        let total_shards = data_shards + parity_shards;
+
+        // Build an `total_shards x data_shards` Cauchy matrix. `x` and `y` are disjoint sets of
+        // distinct values, so every entry is well-defined and every square submatrix of the
+        // result is guaranteed to be invertible.
+        let cauchy = (0..total_shards)
+            .map(|i| {
+                (0..data_shards)
+                    .map(|j| {
+                        let y = (total_shards + j) as u8;
+                        gf.div(1, i as u8 ^ y)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        // Make the code systematic by left-multiplying by the inverse of the top `data_shards`
+        // rows, so the first `data_shards` rows of the result become the identity matrix.
+        let top = &cauchy[..data_shards];
+        let top_inverse = gf.invert(top);
+
+        let matrix = cauchy
+            .iter()
+            .map(|row| {
+                (0..data_shards)
+                    .map(|col| {
+                        (0..data_shards).fold(0u8, |acc, k| acc ^ gf.mul(row[k], top_inverse[k][col]))
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        Self {
+            data_shards,
+            parity_shards,
+            gf,
+            matrix,
+        }
+    }
+
+    /// The number of data shards in a stripe encoded with this code.
+    pub fn data_shards(&self) -> usize {
+        self.data_shards
+    }
+
+    /// The number of parity shards in a stripe encoded with this code.
+    pub fn parity_shards(&self) -> usize {
+        self.parity_shards
+    }
+
+    /// Compute the parity shards for `data`, which must contain exactly `data_shards` shards, all
+    /// of the same length (callers should zero-pad data shards to a common length first).
+    ///
+    /// # Errors
+    /// `RsError::MismatchedShardLength` if the data shards aren't all the same length.
+    pub fn encode(&self, data: &[Vec<u8>]) -> Result<Vec<Vec<u8>>, RsError> {
+        assert_eq!(data.len(), self.data_shards, "wrong number of data shards");
+
+        let shard_len = data[0].len();
+        if data.iter().any(|shard| shard.len() != shard_len) {
+            return Err(RsError::MismatchedShardLength);
+        }
+
+        let parity = (0..self.parity_shards)
+            .map(|p| {
+                let coefficients = &self.matrix[self.data_shards + p];
+                (0..shard_len)
+                    .map(|byte| {
+                        (0..self.data_shards).fold(0u8, |acc, j| {
+                            acc ^ self.gf.mul(coefficients[j], data[j][byte])
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        Ok(parity)
+    }
+
+    /// Reconstruct any missing shards in `shards`, which must have exactly `data_shards +
+    /// parity_shards` elements, with a `None` entry for each lost shard.
+    ///
+    /// On success, every `None` entry in `shards` has been replaced with its recovered content.
+    ///
+    /// # Errors
+    /// - `RsError::NotEnoughShards`: Fewer than `data_shards` shards were present.
+    /// - `RsError::MismatchedShardLength`: The shards that were present weren't all the same
+    /// length.
+    pub fn reconstruct(&self, shards: &mut [Option<Vec<u8>>]) -> Result<(), RsError> {
+        let total_shards = self.data_shards + self.parity_shards;
+        assert_eq!(shards.len(), total_shards, "wrong number of shards");
+
+        let present = shards
+            .iter()
+            .enumerate()
+            .filter_map(|(index, shard)| shard.as_ref().map(|_| index))
+            .collect::<Vec<_>>();
+
+        if present.len() < self.data_shards {
+            return Err(RsError::NotEnoughShards);
+        }
+
+        let shard_len = shards[present[0]].as_ref().unwrap().len();
+        if present
+            .iter()
+            .any(|&index| shards[index].as_ref().unwrap().len() != shard_len)
+        {
+            return Err(RsError::MismatchedShardLength);
+        }
+
+        // Any `data_shards` of the present shards are enough, since every square submatrix of a
+        // Cauchy matrix is invertible.
+        let chosen = &present[..self.data_shards];
+
+        let submatrix = chosen
+            .iter()
+            .map(|&index| self.matrix[index].clone())
+            .collect::<Vec<_>>();
+        let inverse = self.gf.invert(&submatrix);
+
+        // Recover every missing data shard from the chosen surviving shards.
+        for data_index in 0..self.data_shards {
+            if shards[data_index].is_some() {
+                continue;
+            }
+
+            let recovered = (0..shard_len)
+                .map(|byte| {
+                    (0..self.data_shards).fold(0u8, |acc, row| {
+                        let value = shards[chosen[row]].as_ref().unwrap()[byte];
+                        acc ^ self.gf.mul(inverse[data_index][row], value)
+                    })
+                })
+                .collect();
+
+            shards[data_index] = Some(recovered);
+        }
+
+        // Recompute any missing parity shards now that every data shard is known.
+        for p in 0..self.parity_shards {
+            let parity_index = self.data_shards + p;
+            if shards[parity_index].is_some() {
+                continue;
+            }
+
+            let coefficients = &self.matrix[parity_index];
+            let recomputed = (0..shard_len)
+                .map(|byte| {
+                    (0..self.data_shards).fold(0u8, |acc, j| {
+                        acc ^ self.gf.mul(coefficients[j], shards[j].as_ref().unwrap()[byte])
+                    })
+                })
+                .collect();
+
+            shards[parity_index] = Some(recomputed);
+        }
+
+        Ok(())
+    }
+}
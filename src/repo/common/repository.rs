@@ -15,15 +15,20 @@
  */
 
 use std::collections::{HashMap, HashSet};
-use std::io::Write;
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::mem;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use hex_literal::hex;
 use rmp_serde::{from_read, to_vec};
 use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::redundancy::RsCode;
 use crate::repo::{OpenRepo, Packing};
 use crate::store::DataStore;
 
@@ -46,6 +51,208 @@ pub(super) const METADATA_BLOCK_ID: Uuid =
 pub(super) const VERSION_BLOCK_ID: Uuid =
     Uuid::from_bytes(hex!("cbf28b1c 3550 11ea 8cb0 87d7a14efe10"));
 
+/// The block ID of the block which stores the in-progress repack journal, if any.
+///
+/// See [`ObjectRepo::clean_with`] for what this is used for.
+///
+/// [`ObjectRepo::clean_with`]: crate::repo::object::ObjectRepo::clean_with
+pub(super) const REPACK_JOURNAL_BLOCK_ID: Uuid =
+    Uuid::from_bytes(hex!("a3ecbf04 0bec 4408 a3a2 8c7250873057"));
+
+/// Options controlling how [`ObjectRepo::verify_with`] scans the repository.
+///
+/// [`ObjectRepo::verify_with`]: crate::repo::object::ObjectRepo::verify_with
+#[derive(Debug, Clone)]
+pub struct VerifyOptions {
+    /// The number of worker threads to dispatch chunk reads across.
+    pub threads: usize,
+
+    /// A token which, when set to `true` from another thread, cancels the scan as soon as the
+    /// currently in-flight chunk reads finish.
+    pub cancel_token: Arc<AtomicBool>,
+}
+
+impl Default for VerifyOptions {
+    fn default() -> Self {
+        Self {
+            threads: 4,
+            cancel_token: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+/// A progress update reported by [`ObjectRepo::verify_with`] after each chunk is checked.
+///
+/// [`ObjectRepo::verify_with`]: crate::repo::object::ObjectRepo::verify_with
+#[derive(Debug, Clone, Copy)]
+pub struct VerifyProgress {
+    /// The number of chunks checked so far.
+    pub chunks_checked: usize,
+
+    /// The total number of chunks that will be checked.
+    pub total_chunks: usize,
+
+    /// The number of corrupt chunks found so far.
+    pub corrupt_chunks: usize,
+}
+
+/// How a repository protects its data blocks against bit rot.
+///
+/// This is part of the repository [`Config`](crate::repo::object::Config) and takes effect the
+/// next time [`commit`] is called, which groups every data block not yet in a stripe into new
+/// stripes, including blocks written before redundancy was enabled. Stripes formed earlier are
+/// kept as they are.
+///
+/// [`commit`]: crate::repo::object::ObjectRepo::commit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Redundancy {
+    /// Don't compute parity for data blocks.
+    ///
+    /// A corrupt block can only be detected by [`verify`], not repaired by [`repair`].
+    ///
+    /// [`verify`]: crate::repo::object::ObjectRepo::verify
+    /// [`repair`]: crate::repo::object::ObjectRepo::repair
+    None,
+
+    /// Group data blocks into stripes of `data_blocks` blocks and protect each stripe with
+    /// `parity_blocks` parity blocks, computed with a Reed-Solomon code over `GF(2^8)`.
+    ///
+    /// A stripe can be fully recovered as long as no more than `parity_blocks` of its members
+    /// (data or parity) are lost.
+    ReedSolomon {
+        /// The number of data blocks per stripe.
+        data_blocks: usize,
+
+        /// The number of parity blocks computed for each stripe.
+        parity_blocks: usize,
+    },
+}
+
+impl Default for Redundancy {
+    fn default() -> Self {
+        Self::None
+    }
+}
+
+/// A group of data blocks which are protected together by Reed-Solomon parity.
+///
+/// Losing up to as many blocks as the stripe has parity blocks, of either kind, is recoverable by
+/// [`ObjectRepo::repair`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Stripe {
+    /// The IDs of the data blocks in this stripe, in the order they were encoded.
+    pub data_blocks: Vec<Uuid>,
+
+    /// The real, pre-padding length of each block in `data_blocks`, in the same order.
+    ///
+    /// The encoder requires every shard in a stripe to be the same length, so shorter blocks are
+    /// zero-padded out to the stripe's shard length before encoding. This records each block's
+    /// real length so a reconstructed block can be truncated back to it, rather than writing back
+    /// the padded shard and corrupting the block.
+    pub data_block_lengths: Vec<u64>,
+
+    /// The IDs of the parity blocks computed over `data_blocks`.
+    pub parity_blocks: Vec<Uuid>,
+}
+
+/// A checkpoint recording the progress of an in-progress repack, so [`ObjectRepo::clean_with`] can
+/// resume after being interrupted instead of redoing already-migrated work.
+///
+/// [`ObjectRepo::clean_with`]: crate::repo::object::ObjectRepo::clean_with
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct RepackJournal {
+    /// The IDs of packs slated for removal once every block in `pending_blocks` has been
+    /// migrated out of them.
+    packs_to_remove: Vec<Uuid>,
+
+    /// The IDs of referenced blocks which still need to be read from their old pack and
+    /// rewritten into a new one.
+    pending_blocks: Vec<Uuid>,
+}
+
+/// Options controlling how [`ObjectRepo::clean_with`] repacks the repository.
+///
+/// [`ObjectRepo::clean_with`]: crate::repo::object::ObjectRepo::clean_with
+#[derive(Debug, Clone)]
+pub struct CleanOptions {
+    /// A token which, when set to `true` from another thread, cancels the repack as soon as the
+    /// block currently being migrated, or the pack currently being removed, finishes.
+    ///
+    /// Progress up to that point is saved in the repack journal, so the next call to
+    /// [`clean_with`] resumes instead of starting over.
+    ///
+    /// [`clean_with`]: crate::repo::object::ObjectRepo::clean_with
+    pub cancel_token: Arc<AtomicBool>,
+}
+
+impl Default for CleanOptions {
+    fn default() -> Self {
+        Self {
+            cancel_token: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+/// A progress update reported by [`ObjectRepo::clean_with`] as it removes each stale pack.
+///
+/// [`ObjectRepo::clean_with`]: crate::repo::object::ObjectRepo::clean_with
+#[derive(Debug, Clone, Copy)]
+pub struct CleanProgress {
+    /// The number of stale packs removed so far.
+    pub packs_processed: usize,
+
+    /// The total number of stale packs that will be removed.
+    pub total_packs: usize,
+}
+
+/// A record in a snapshot's offset table, giving the byte range of one still-encoded block.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SnapshotEntry {
+    block_id: Uuid,
+    offset: u64,
+    length: u64,
+}
+
+/// The trailer written at the very end of a snapshot, letting [`ObjectRepo::import_snapshot`]
+/// locate the offset table using only `Seek`, without scanning the whole stream.
+///
+/// [`ObjectRepo::import_snapshot`]: crate::repo::object::ObjectRepo::import_snapshot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SnapshotFooter {
+    table_offset: u64,
+    table_length: u64,
+}
+
+/// Options controlling [`ObjectRepo::import_snapshot`].
+///
+/// [`ObjectRepo::import_snapshot`]: crate::repo::object::ObjectRepo::import_snapshot
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    /// A token which, when set to `true` from another thread, cancels the import as soon as the
+    /// block currently being validated and written finishes.
+    pub cancel_token: Arc<AtomicBool>,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            cancel_token: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+/// The outcome of calling [`ObjectRepo::repair`].
+#[derive(Debug, Clone, Default)]
+pub struct RepairReport {
+    /// The IDs of blocks that were corrupt but have been successfully reconstructed from parity.
+    pub repaired_blocks: HashSet<Uuid>,
+
+    /// The IDs of blocks that were corrupt and could not be reconstructed, either because
+    /// redundancy is disabled or because their stripe suffered more losses than it has parity
+    /// blocks to recover from.
+    pub lost_blocks: HashSet<Uuid>,
+}
+
 /// A low-level repository type which provides more direct access to the underlying storage.
 ///
 /// See [`crate::repo::object`] for more information.
@@ -364,6 +571,7 @@ impl ObjectRepo {
             .filter(|id| {
                 *id != METADATA_BLOCK_ID
                     && *id != VERSION_BLOCK_ID
+                    && *id != REPACK_JOURNAL_BLOCK_ID
                     && *id != self.state.metadata.header_id
             })
             .collect())
@@ -400,6 +608,7 @@ impl ObjectRepo {
         Header {
             chunks: self.state.chunks.clone(),
             packs: self.state.packs.clone(),
+            stripes: self.state.stripes.clone(),
             managed: self.managed.clone(),
             handle_table: self.handle_table.clone(),
         }
@@ -415,6 +624,7 @@ impl ObjectRepo {
         let header = Header {
             chunks: mem::replace(&mut self.state.chunks, HashMap::new()),
             packs: mem::replace(&mut self.state.packs, HashMap::new()),
+            stripes: mem::replace(&mut self.state.stripes, Vec::new()),
             managed: mem::replace(&mut self.managed, HashMap::new()),
             handle_table: mem::replace(&mut self.handle_table, IdTable::new()),
         };
@@ -427,11 +637,13 @@ impl ObjectRepo {
         let Header {
             chunks,
             packs,
+            stripes,
             managed,
             handle_table,
         } = header;
         self.state.chunks = chunks;
         self.state.packs = packs;
+        self.state.stripes = stripes;
         self.managed = managed;
         self.handle_table = handle_table;
 
@@ -442,10 +654,93 @@ impl ObjectRepo {
     fn restore_header(&mut self, header: Header) {
         self.state.chunks = header.chunks;
         self.state.packs = header.packs;
+        self.state.stripes = header.stripes;
         self.managed = header.managed;
         self.handle_table = header.handle_table;
     }
 
+    /// Group any data blocks which aren't yet part of a stripe into new, fully-populated stripes
+    /// and compute their parity.
+    ///
+    /// Blocks are left ungrouped until there are enough of them to fill a whole stripe; any
+    /// leftover is picked up again the next time this is called. Does nothing if redundancy is
+    /// disabled.
+    fn emit_parity(&mut self) -> crate::Result<()> {
+        let (data_count, parity_count) = match self.state.metadata.config.redundancy {
+            Redundancy::None => return Ok(()),
+            Redundancy::ReedSolomon {
+                data_blocks,
+                parity_blocks,
+            } => (data_blocks, parity_blocks),
+        };
+
+        let already_striped = self
+            .state
+            .stripes
+            .iter()
+            .flat_map(|stripe| stripe.data_blocks.iter().chain(stripe.parity_blocks.iter()))
+            .copied()
+            .collect::<HashSet<_>>();
+
+        let unstriped_blocks = self
+            .list_data_blocks()?
+            .into_iter()
+            .filter(|block_id| !already_striped.contains(block_id))
+            .collect::<Vec<_>>();
+
+        let code = RsCode::new(data_count, parity_count);
+
+        for group in unstriped_blocks.chunks(data_count) {
+            if group.len() < data_count {
+                // Not enough new blocks yet to fill a whole stripe.
+                break;
+            }
+
+            let mut payloads = Vec::with_capacity(data_count);
+            {
+                let mut store = self.state.store.lock().unwrap();
+                for block_id in group {
+                    let payload = store
+                        .read_block(*block_id)
+                        .map_err(|error| crate::Error::Store(error))?
+                        .ok_or(crate::Error::Corrupt)?;
+                    payloads.push(payload);
+                }
+            }
+
+            // The encoder requires every shard to be the same length.
+            let data_block_lengths = payloads.iter().map(|payload| payload.len() as u64).collect();
+            let shard_len = payloads.iter().map(Vec::len).max().unwrap_or(0);
+            for payload in &mut payloads {
+                payload.resize(shard_len, 0);
+            }
+
+            let parity = code
+                .encode(&payloads)
+                .expect("data shards passed to the encoder were not all the same length");
+
+            let mut parity_blocks = Vec::with_capacity(parity_count);
+            {
+                let mut store = self.state.store.lock().unwrap();
+                for shard in parity {
+                    let parity_id = Uuid::new_v4();
+                    store
+                        .write_block(parity_id, shard.as_slice())
+                        .map_err(|error| crate::Error::Store(error))?;
+                    parity_blocks.push(parity_id);
+                }
+            }
+
+            self.state.stripes.push(Stripe {
+                data_blocks: group.to_vec(),
+                data_block_lengths,
+                parity_blocks,
+            });
+        }
+
+        Ok(())
+    }
+
     /// Commit changes which have been made to the repository.
     ///
     /// No changes are saved persistently until this method is called.
@@ -479,6 +774,9 @@ impl ObjectRepo {
             self.remove_unmanaged(&handle);
         }
 
+        // Group any data blocks not yet in a stripe into new stripes and emit their parity.
+        self.emit_parity()?;
+
         // Serialize the header.
         let serialized_header = self.serialize_header();
 
@@ -618,6 +916,59 @@ impl ObjectRepo {
         Ok(())
     }
 
+    /// Read the in-progress repack journal from the data store, if one exists.
+    ///
+    /// Returns `None` if no `clean_with` call is currently in the middle of repacking.
+    fn read_repack_journal(&self) -> crate::Result<Option<RepackJournal>> {
+        let encoded_journal = match self
+            .state
+            .store
+            .lock()
+            .unwrap()
+            .read_block(REPACK_JOURNAL_BLOCK_ID)
+            .map_err(|error| crate::Error::Store(error))?
+        {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        let serialized_journal = self.state.decode_data(encoded_journal.as_slice())?;
+        let journal =
+            from_read(serialized_journal.as_slice()).map_err(|_| crate::Error::Corrupt)?;
+        Ok(Some(journal))
+    }
+
+    /// Persist `journal` to the data store, overwriting any previous journal.
+    fn write_repack_journal(&mut self, journal: &RepackJournal) -> crate::Result<()> {
+        let serialized_journal =
+            to_vec(journal).expect("Could not serialize the repack journal.");
+        let encoded_journal = self.state.encode_data(serialized_journal.as_slice())?;
+        self.state
+            .store
+            .lock()
+            .unwrap()
+            .write_block(REPACK_JOURNAL_BLOCK_ID, encoded_journal.as_slice())
+            .map_err(|error| crate::Error::Store(error))?;
+        Ok(())
+    }
+
+    /// Remove the repack journal from the data store, once a repack has finished.
+    ///
+    /// This is a no-op if there is no journal block, so it's safe to call even if `clean_with` was
+    /// never interrupted.
+    fn clear_repack_journal(&mut self) -> crate::Result<()> {
+        let mut store = self.state.store.lock().unwrap();
+        let journal_exists = store
+            .read_block(REPACK_JOURNAL_BLOCK_ID)
+            .map_err(|error| crate::Error::Store(error))?
+            .is_some();
+        if journal_exists {
+            store
+                .remove_block(REPACK_JOURNAL_BLOCK_ID)
+                .map_err(|error| crate::Error::Store(error))?;
+        }
+        Ok(())
+    }
+
     /// Clean up the repository to reclaim space in the backing data store.
     ///
     /// When data in a repository is deleted, the space is not reclaimed in the backing data store
@@ -629,6 +980,35 @@ impl ObjectRepo {
     /// - `Error::Store`: An error occurred with the data store.
     /// - `Error::Io`: An I/O error occurred.
     pub fn clean(&mut self) -> crate::Result<()> {
+        self.clean_with(CleanOptions::default(), |_| {})
+    }
+
+    /// Clean up the repository to reclaim space in the backing data store.
+    ///
+    /// This behaves like [`clean`], but checkpoints its progress while repacking so that an
+    /// interrupted repack can resume where it left off instead of redoing already-migrated work,
+    /// and reports progress to `on_progress` after each pack is processed. Set `opts.cancel_token`
+    /// and flip it from another thread to stop cleanly between packs; the interrupted repack can
+    /// be resumed by calling `clean_with` again.
+    ///
+    /// Only the `Packing::Fixed` repacking path is checkpointed, since it's the only one whose work
+    /// is expensive enough to be worth resuming; the `Packing::None` path just removes
+    /// already-unreferenced blocks directly and has nothing to checkpoint. Resumption is only
+    /// detected here, at the start of a `clean_with` call — not when the repository is opened.
+    ///
+    /// # Errors
+    /// - `Error::Cancelled`: `opts.cancel_token` was set before the repack finished.
+    /// - `Error::Corrupt`: The repository is corrupt. This is most likely unrecoverable.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`clean`]: crate::repo::object::ObjectRepo::clean
+    pub fn clean_with(
+        &mut self,
+        opts: CleanOptions,
+        mut on_progress: impl FnMut(CleanProgress),
+    ) -> crate::Result<()> {
         // Read the header from the previous commit.
         let encoded_header = self
             .state
@@ -656,6 +1036,13 @@ impl ObjectRepo {
         let previous_referenced_blocks = previous_header.chunks.values().map(|info| info.block_id);
         referenced_blocks.extend(previous_referenced_blocks);
 
+        // Parity blocks aren't referenced by any chunk, so without this they'd look orphaned to
+        // the cleanup logic below. Stripes that no longer cover blocks present in the data store
+        // are pruned separately, after repacking, once we know which blocks actually survived.
+        for stripe in self.state.stripes.iter().chain(previous_header.stripes.iter()) {
+            referenced_blocks.extend(stripe.parity_blocks.iter().copied());
+        }
+
         // Remove all blocks from the data store which are unreferenced.
         match &self.state.metadata.config.packing {
             Packing::None => {
@@ -671,74 +1058,143 @@ impl ObjectRepo {
                             .map_err(|error| crate::Error::Store(error))?;
                     }
                 }
+                drop(store);
+
+                // Recompute the stripes now that we know which blocks actually remain.
+                self.resync_stripes()?;
+                let mut previous_header = previous_header;
+                previous_header.stripes = mem::replace(&mut self.state.stripes, Vec::new());
+                let serialized_header = to_vec(&previous_header)
+                    .expect("Could not serialize the repository header.");
+                mem::swap(&mut previous_header.stripes, &mut self.state.stripes);
+                drop(previous_header);
+                let encoded_header = self.state.encode_data(serialized_header.as_slice())?;
+                self.write_serialized_header(encoded_header.as_slice())?;
             }
             Packing::Fixed(_) => {
                 // When packing is enabled, we need to repack the packs which contain unreferenced
                 // blocks.
 
-                // Get an iterator of block IDs and the list of packs they're contained in.
-                let blocks_to_packs = self.state.packs.iter().chain(previous_header.packs.iter());
-
-                // Get a map of pack IDs to the set of blocks contained in them.
-                let mut packs_to_blocks = HashMap::new();
-                for (block_id, index_list) in blocks_to_packs {
-                    for pack_index in index_list {
-                        packs_to_blocks
-                            .entry(pack_index.id)
-                            .or_insert_with(HashSet::new)
-                            .insert(*block_id);
-                    }
-                }
+                // Resume an interrupted repack from its journal if one exists, rather than
+                // recomputing which packs and blocks need repacking from scratch.
+                let (mut packs_to_remove, mut pending_blocks) = match self.read_repack_journal()? {
+                    Some(journal) => (journal.packs_to_remove, journal.pending_blocks),
+                    None => {
+                        // Get an iterator of block IDs and the list of packs they're contained in.
+                        let blocks_to_packs =
+                            self.state.packs.iter().chain(previous_header.packs.iter());
+
+                        // Get a map of pack IDs to the set of blocks contained in them.
+                        let mut packs_to_blocks = HashMap::new();
+                        for (block_id, index_list) in blocks_to_packs {
+                            for pack_index in index_list {
+                                packs_to_blocks
+                                    .entry(pack_index.id)
+                                    .or_insert_with(HashSet::new)
+                                    .insert(*block_id);
+                            }
+                        }
 
-                // The list of IDs of packs which contain at least one unreferenced block.
-                let mut packs_to_remove = Vec::new();
-
-                // The list of blocks which need to be repacked. These are referenced blocks which
-                // are contained in packs which contain at least one unreferenced block.
-                let mut blocks_to_repack = Vec::new();
-
-                // Iterate over the IDs of packs which are contained in the data store.
-                for pack_id in self.list_data_blocks()? {
-                    match packs_to_blocks.get(&pack_id) {
-                        Some(contained_blocks) => {
-                            let contains_unreferenced_blocks = contained_blocks
-                                .iter()
-                                .any(|block_id| !referenced_blocks.contains(block_id));
-                            if contains_unreferenced_blocks {
-                                let contained_referenced_blocks =
-                                    contained_blocks.intersection(&referenced_blocks).copied();
-                                packs_to_remove.push(pack_id);
-                                blocks_to_repack.extend(contained_referenced_blocks);
+                        // The list of IDs of packs which contain at least one unreferenced block.
+                        let mut packs_to_remove = Vec::new();
+
+                        // The list of blocks which need to be repacked. These are referenced blocks
+                        // which are contained in packs which contain at least one unreferenced
+                        // block.
+                        let mut pending_blocks = Vec::new();
+
+                        // Iterate over the IDs of packs which are contained in the data store.
+                        for pack_id in self.list_data_blocks()? {
+                            match packs_to_blocks.get(&pack_id) {
+                                Some(contained_blocks) => {
+                                    let contains_unreferenced_blocks = contained_blocks
+                                        .iter()
+                                        .any(|block_id| !referenced_blocks.contains(block_id));
+                                    if contains_unreferenced_blocks {
+                                        let contained_referenced_blocks = contained_blocks
+                                            .intersection(&referenced_blocks)
+                                            .copied();
+                                        packs_to_remove.push(pack_id);
+                                        pending_blocks.extend(contained_referenced_blocks);
+                                    }
+                                }
+                                // A parity block is in no pack, but it is still referenced.
+                                None if referenced_blocks.contains(&pack_id) => {}
+                                // This pack does not contain any blocks that we know about. We can
+                                // remove it.
+                                None => packs_to_remove.push(pack_id),
                             }
                         }
-                        // This pack does not contain any blocks that we know about. We can remove
-                        // it.
-                        None => packs_to_remove.push(pack_id),
+
+                        // Persist the plan before doing any work, so an interruption after this
+                        // point can resume instead of recomputing it.
+                        self.write_repack_journal(&RepackJournal {
+                            packs_to_remove: packs_to_remove.clone(),
+                            pending_blocks: pending_blocks.clone(),
+                        })?;
+
+                        (packs_to_remove, pending_blocks)
                     }
-                }
+                };
+
+                let total_packs = packs_to_remove.len();
 
                 // For each block that needs repacking, read it from its current pack and write it
-                // to a new one.
+                // to a new one. Blocks are removed from the journal's pending list as they're
+                // migrated, so if this is interrupted, the next call can skip the blocks already
+                // done.
                 {
                     let mut store_state = StoreState::new();
                     let mut store_writer = StoreWriter::new(&mut self.state, &mut store_state);
-                    for block_id in blocks_to_repack {
+                    while !pending_blocks.is_empty() {
+                        // Check for cancellation before popping, so a block we've committed to
+                        // isn't dropped from the journal without actually being migrated.
+                        if opts.cancel_token.load(Ordering::Relaxed) {
+                            drop(store_writer);
+                            self.write_repack_journal(&RepackJournal {
+                                packs_to_remove: packs_to_remove.clone(),
+                                pending_blocks,
+                            })?;
+                            return Err(crate::Error::Cancelled);
+                        }
+                        let block_id = pending_blocks.pop().unwrap();
                         let block_data = store_writer.read_block(block_id)?;
                         store_writer.write_block(block_id, block_data.as_slice())?;
                     }
                 }
 
                 // Once all the referenced blocks have been written to new packs, remove the old
-                // packs from the data store.
+                // packs from the data store, reporting progress as we go.
                 {
                     let mut store = self.state.store.lock().unwrap();
-                    for pack_id in packs_to_remove {
+                    let mut packs_processed = 0;
+                    while !packs_to_remove.is_empty() {
+                        // Check for cancellation before popping, so a pack we've committed to
+                        // isn't dropped from the journal without actually being removed.
+                        if opts.cancel_token.load(Ordering::Relaxed) {
+                            drop(store);
+                            self.write_repack_journal(&RepackJournal {
+                                packs_to_remove,
+                                pending_blocks: Vec::new(),
+                            })?;
+                            return Err(crate::Error::Cancelled);
+                        }
+                        let pack_id = packs_to_remove.pop().unwrap();
                         store
                             .remove_block(pack_id)
                             .map_err(|error| crate::Error::Store(error))?;
+                        packs_processed += 1;
+                        on_progress(CleanProgress {
+                            packs_processed,
+                            total_packs,
+                        });
                     }
                 }
 
+                // Every old pack has been removed, so the repack finished successfully and there's
+                // nothing left to resume.
+                self.clear_repack_journal()?;
+
                 // Once old packs have been removed from the data store, all unreferenced blocks
                 // have been removed from the data store. At this point, we can remove those
                 // blocks from the pack map. Because block IDs are random UUIDs and are
@@ -752,19 +1208,25 @@ impl ObjectRepo {
                     .packs
                     .retain(|block_id, _| referenced_blocks.contains(block_id));
 
+                // Recompute the stripes now that we know which blocks survived repacking.
+                self.resync_stripes()?;
+
                 // Next we need to write the updated pack map to the data store. To do this, we have
                 // to write the entire header. Because this method does not commit any changes, it's
-                // important that we write the previous header, changing only the pack map.
+                // important that we write the previous header, changing only the pack map and
+                // stripe list.
                 {
                     let mut previous_header = previous_header;
 
-                    // Temporarily move the pack map into the previous header just so that we can
-                    // serialize it. Once we're done, move it back. This avoids needing the clone
-                    // the pack map.
+                    // Temporarily move the pack map and stripe list into the previous header just
+                    // so that we can serialize it. Once we're done, move them back. This avoids
+                    // needing to clone them.
                     previous_header.packs = mem::replace(&mut self.state.packs, HashMap::new());
+                    previous_header.stripes = mem::replace(&mut self.state.stripes, Vec::new());
                     let serialized_header = to_vec(&previous_header)
                         .expect("Could not serialize the repository header.");
                     mem::swap(&mut previous_header.packs, &mut self.state.packs);
+                    mem::swap(&mut previous_header.stripes, &mut self.state.stripes);
                     drop(previous_header);
 
                     // Encode the serialized header and write it to the data store.
@@ -777,6 +1239,46 @@ impl ObjectRepo {
         Ok(())
     }
 
+    /// Drop any stripe that no longer fully covers blocks present in the data store, remove its
+    /// now-orphaned parity blocks, and form new stripes (with fresh parity) over whatever is left
+    /// unstriped.
+    ///
+    /// This is called by [`clean`] after repacking, since repacking can change which blocks are
+    /// present in the data store out from under an existing stripe.
+    ///
+    /// [`clean`]: crate::repo::object::ObjectRepo::clean
+    fn resync_stripes(&mut self) -> crate::Result<()> {
+        let current_blocks = self.list_data_blocks()?.into_iter().collect::<HashSet<_>>();
+
+        let (valid, invalidated): (Vec<Stripe>, Vec<Stripe>) =
+            mem::replace(&mut self.state.stripes, Vec::new())
+                .into_iter()
+                .partition(|stripe| {
+                    stripe
+                        .data_blocks
+                        .iter()
+                        .chain(stripe.parity_blocks.iter())
+                        .all(|block_id| current_blocks.contains(block_id))
+                });
+        self.state.stripes = valid;
+
+        let mut store = self.state.store.lock().unwrap();
+        for stripe in invalidated {
+            // The data blocks are either still referenced elsewhere or already gone; only the
+            // parity blocks are solely owned by the stripe itself.
+            for parity_id in stripe.parity_blocks {
+                if current_blocks.contains(&parity_id) {
+                    store
+                        .remove_block(parity_id)
+                        .map_err(|error| crate::Error::Store(error))?;
+                }
+            }
+        }
+        drop(store);
+
+        self.emit_parity()
+    }
+
     /// Delete all data in all instances of the repository.
     ///
     /// No data is reclaimed in the backing data store until changes are committed and [`clean`] is
@@ -809,53 +1311,129 @@ impl ObjectRepo {
     ///
     /// [`Object::verify`]: crate::repo::Object::verify
     pub fn verify(&self) -> crate::Result<IntegrityReport> {
-        let mut report = IntegrityReport {
-            corrupt_chunks: HashSet::new(),
-            corrupt_managed: HashMap::new(),
-        };
+        self.verify_with(VerifyOptions::default(), |_| {})
+    }
 
+    /// Verify the integrity of all the data in every instance of the repository.
+    ///
+    /// This behaves like [`verify`], but dispatches chunk reads across a pool of `opts.threads`
+    /// worker threads (each with its own `StoreReader`, since the backing store is
+    /// `Mutex`-guarded) and reports progress to `on_progress` after every chunk. Set
+    /// `opts.cancel_token` and flip it from another thread to abort the scan early; an aborted
+    /// scan returns `Error::Cancelled` without having modified any repository state.
+    ///
+    /// This is meant for multi-terabyte repositories where a single-threaded, blocking [`verify`]
+    /// is impractical.
+    ///
+    /// # Errors
+    /// - `Error::Cancelled`: `opts.cancel_token` was set before the scan finished.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`verify`]: crate::repo::object::ObjectRepo::verify
+    pub fn verify_with(
+        &self,
+        opts: VerifyOptions,
+        mut on_progress: impl FnMut(VerifyProgress),
+    ) -> crate::Result<IntegrityReport> {
         let expected_chunks = self.state.chunks.keys().copied().collect::<Vec<_>>();
+        let total_chunks = expected_chunks.len();
+
+        let work = Mutex::new(expected_chunks.into_iter());
+        // A rendezvous channel: a worker can't start its next chunk until the last result it
+        // produced has been taken, so pausing in `on_progress` also pauses the workers.
+        let (result_tx, result_rx) = mpsc::sync_channel(0);
+        let thread_count = opts.threads.max(1);
+
+        thread::scope(|scope| {
+            for _ in 0..thread_count {
+                let work = &work;
+                let cancel_token = &opts.cancel_token;
+                let result_tx = result_tx.clone();
+                let state = &self.state;
+
+                scope.spawn(move || {
+                    let mut store_state = StoreState::new();
+                    let mut store_reader = StoreReader::new(state, &mut store_state);
+
+                    loop {
+                        if cancel_token.load(Ordering::Relaxed) {
+                            let _ = result_tx.send(Err(crate::Error::Cancelled));
+                            return;
+                        }
+
+                        let chunk = match work.lock().unwrap().next() {
+                            Some(chunk) => chunk,
+                            None => return,
+                        };
+
+                        let outcome = match store_reader.read_chunk(chunk) {
+                            Ok(data) => {
+                                let is_corrupt =
+                                    data.len() != chunk.size as usize || chunk_hash(&data) != chunk.hash;
+                                Ok((chunk, is_corrupt))
+                            }
+                            // Ciphertext verification failed. No need to check the hash.
+                            Err(crate::Error::InvalidData) => Ok((chunk, true)),
+                            Err(error) => Err(error),
+                        };
 
-        // Get the set of hashes of chunks which are corrupt.
-        let mut store_state = StoreState::new();
-        let mut store_reader = StoreReader::new(&self.state, &mut store_state);
-        for chunk in expected_chunks {
-            match store_reader.read_chunk(chunk) {
-                Ok(data) => {
-                    if data.len() != chunk.size as usize || chunk_hash(&data) != chunk.hash {
-                        report.corrupt_chunks.insert(chunk.hash);
+                        if result_tx.send(outcome).is_err() {
+                            return;
+                        }
                     }
-                }
-                Err(crate::Error::InvalidData) => {
-                    // Ciphertext verification failed. No need to check the hash.
+                });
+            }
+
+            // Drop our own sender so the receiver's iterator ends once every worker has finished.
+            drop(result_tx);
+
+            let mut report = IntegrityReport {
+                corrupt_chunks: HashSet::new(),
+                corrupt_managed: HashMap::new(),
+            };
+            let mut chunks_checked = 0;
+
+            for result in result_rx {
+                let (chunk, is_corrupt) = result?;
+                chunks_checked += 1;
+                if is_corrupt {
                     report.corrupt_chunks.insert(chunk.hash);
                 }
-                Err(error) => return Err(error),
-            };
-        }
+                on_progress(VerifyProgress {
+                    chunks_checked,
+                    total_chunks,
+                    corrupt_chunks: report.corrupt_chunks.len(),
+                });
+            }
 
-        // If there are no corrupt chunks, there are no corrupt objects.
-        if report.corrupt_chunks.is_empty() {
-            return Ok(report);
-        }
+            Ok(report)
+        })
+        .map(|mut report| {
+            // If there are no corrupt chunks, there are no corrupt objects.
+            if report.corrupt_chunks.is_empty() {
+                return report;
+            }
 
-        for (instance_id, managed) in &self.managed {
-            for (object_id, handle) in managed {
-                for chunk in &handle.chunks {
-                    // If any one of the object's chunks is corrupt, the object is corrupt.
-                    if report.corrupt_chunks.contains(&chunk.hash) {
-                        report
-                            .corrupt_managed
-                            .entry(*instance_id)
-                            .or_default()
-                            .insert(*object_id);
-                        break;
+            for (instance_id, managed) in &self.managed {
+                for (object_id, handle) in managed {
+                    for chunk in &handle.chunks {
+                        // If any one of the object's chunks is corrupt, the object is corrupt.
+                        if report.corrupt_chunks.contains(&chunk.hash) {
+                            report
+                                .corrupt_managed
+                                .entry(*instance_id)
+                                .or_default()
+                                .insert(*object_id);
+                            break;
+                        }
                     }
                 }
             }
-        }
 
-        Ok(report)
+            report
+        })
     }
 
     /// Change the password for this repository.
@@ -890,4 +1468,672 @@ impl ObjectRepo {
     pub fn info(&self) -> RepoInfo {
         self.state.metadata.to_info()
     }
+
+    /// Set how this repository protects its data blocks against bit rot.
+    ///
+    /// Existing stripes are kept as they are. The next time [`commit`] is called, every data block
+    /// not yet in a stripe, including blocks written before this call, is grouped into new
+    /// stripes and their parity is emitted; leftover blocks too few to fill a whole stripe stay
+    /// unprotected until enough more are written.
+    ///
+    /// [`commit`]: crate::repo::object::ObjectRepo::commit
+    pub fn set_redundancy(&mut self, redundancy: Redundancy) {
+        self.state.metadata.config.redundancy = redundancy;
+    }
+
+    /// Attempt to repair the corrupt blocks named in `report` using Reed-Solomon parity.
+    ///
+    /// For each corrupt block, this finds the stripe it belongs to. If the stripe has lost no
+    /// more blocks than it has parity blocks, the surviving blocks in the stripe are read and used
+    /// to reconstruct the missing ones. Each reconstructed block is written back to the data store
+    /// and then re-verified against [`verify`]'s own integrity check; a block that fails this check
+    /// stays written but is reported as lost rather than repaired. Stripes that have lost more
+    /// blocks than they have parity for, or whose blocks are not covered by any stripe at all
+    /// (redundancy disabled, or leftover blocks too few to fill a stripe), are reported as lost.
+    ///
+    /// This does not modify `report` or re-scan the repository; call [`verify`] again afterward to
+    /// confirm the repair succeeded.
+    ///
+    /// # Errors
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`verify`]: crate::repo::object::ObjectRepo::verify
+    pub fn repair(&mut self, report: &IntegrityReport) -> crate::Result<RepairReport> {
+        let mut result = RepairReport::default();
+
+        // Map each corrupt chunk hash to the ID of the block that stores it.
+        let corrupt_blocks = self
+            .state
+            .chunks
+            .iter()
+            .filter(|(chunk, _)| report.corrupt_chunks.contains(&chunk.hash))
+            .map(|(_, info)| info.block_id)
+            .collect::<HashSet<_>>();
+
+        if corrupt_blocks.is_empty() {
+            return Ok(result);
+        }
+
+        let (data_count, parity_count) = match self.state.metadata.config.redundancy {
+            Redundancy::None => {
+                result.lost_blocks = corrupt_blocks;
+                return Ok(result);
+            }
+            Redundancy::ReedSolomon {
+                data_blocks,
+                parity_blocks,
+            } => (data_blocks, parity_blocks),
+        };
+        let code = RsCode::new(data_count, parity_count);
+
+        let striped_blocks = self
+            .state
+            .stripes
+            .iter()
+            .flat_map(|stripe| stripe.data_blocks.iter().chain(stripe.parity_blocks.iter()))
+            .copied()
+            .collect::<HashSet<_>>();
+        result
+            .lost_blocks
+            .extend(corrupt_blocks.iter().copied().filter(|id| !striped_blocks.contains(id)));
+
+        for stripe in self.state.stripes.clone() {
+            let original_lengths = stripe
+                .data_blocks
+                .iter()
+                .copied()
+                .zip(stripe.data_block_lengths.iter().copied())
+                .collect::<HashMap<_, _>>();
+            let all_blocks = stripe
+                .data_blocks
+                .iter()
+                .chain(stripe.parity_blocks.iter())
+                .copied()
+                .collect::<Vec<_>>();
+            let corrupt_in_stripe = all_blocks
+                .iter()
+                .filter(|block_id| corrupt_blocks.contains(block_id))
+                .count();
+
+            if corrupt_in_stripe == 0 {
+                continue;
+            }
+
+            if corrupt_in_stripe > parity_count {
+                result.lost_blocks.extend(
+                    all_blocks
+                        .iter()
+                        .copied()
+                        .filter(|block_id| corrupt_blocks.contains(block_id)),
+                );
+                continue;
+            }
+
+            let mut shards = Vec::with_capacity(all_blocks.len());
+            {
+                let mut store = self.state.store.lock().unwrap();
+                for block_id in &all_blocks {
+                    if corrupt_blocks.contains(block_id) {
+                        shards.push(None);
+                        continue;
+                    }
+                    let shard = store
+                        .read_block(*block_id)
+                        .ok()
+                        .flatten();
+                    shards.push(shard);
+                }
+            }
+
+            if code.reconstruct(&mut shards).is_err() {
+                result.lost_blocks.extend(
+                    all_blocks
+                        .iter()
+                        .copied()
+                        .filter(|block_id| corrupt_blocks.contains(block_id)),
+                );
+                continue;
+            }
+
+            // Write back every block we just reconstructed.
+            {
+                let mut store = self.state.store.lock().unwrap();
+                for (index, block_id) in all_blocks.iter().enumerate() {
+                    if !corrupt_blocks.contains(block_id) {
+                        continue;
+                    }
+                    let recovered = shards[index]
+                        .take()
+                        .expect("reconstruct() did not fill in every missing shard");
+                    let recovered = truncate_recovered_shard(recovered, original_lengths.get(block_id).copied());
+                    store
+                        .write_block(*block_id, recovered.as_slice())
+                        .map_err(|error| crate::Error::Store(error))?;
+                    result.repaired_blocks.insert(*block_id);
+                }
+            }
+        }
+
+        // Re-verify every chunk whose block we just repaired; a reconstructed block whose chunk
+        // hash still doesn't match was corrupt before the stripe lost it, and parity alone can't
+        // fix that.
+        if !result.repaired_blocks.is_empty() {
+            let mut store_state = StoreState::new();
+            let mut store_reader = StoreReader::new(&self.state, &mut store_state);
+            let chunks_to_recheck = self
+                .state
+                .chunks
+                .keys()
+                .copied()
+                .filter(|chunk| {
+                    self.state
+                        .chunks
+                        .get(chunk)
+                        .map(|info| result.repaired_blocks.contains(&info.block_id))
+                        .unwrap_or(false)
+                })
+                .collect::<Vec<_>>();
+
+            for chunk in chunks_to_recheck {
+                let is_valid = match store_reader.read_chunk(chunk) {
+                    Ok(data) => data.len() == chunk.size as usize && chunk_hash(&data) == chunk.hash,
+                    Err(_) => false,
+                };
+                if !is_valid {
+                    let block_id = self.state.chunks.get(&chunk).unwrap().block_id;
+                    result.repaired_blocks.remove(&block_id);
+                    result.lost_blocks.insert(block_id);
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Read and deserialize the header from the most recent commit, without touching any
+    /// uncommitted in-memory changes.
+    fn read_committed_header(&self) -> crate::Result<Header> {
+        let encoded_header = self
+            .state
+            .store
+            .lock()
+            .unwrap()
+            .read_block(self.state.metadata.header_id)
+            .map_err(|error| crate::Error::Store(error))?
+            .ok_or(crate::Error::Corrupt)?;
+        let serialized_header = self.state.decode_data(encoded_header.as_slice())?;
+        from_read(serialized_header.as_slice()).map_err(|_| crate::Error::Corrupt)
+    }
+
+    /// Export a complete, self-contained snapshot of this repository to `writer`.
+    ///
+    /// This writes the repository's metadata block, its most recently committed header, and
+    /// every block reachable from that header or the live (possibly uncommitted) state -- the
+    /// union [`clean`] computes as `referenced_blocks`, plus the data blocks of every stripe --
+    /// as length-prefixed, still-encoded
+    /// records, followed by an offset table so [`import_snapshot`] can write them into a new
+    /// store without buffering the whole snapshot in memory.
+    ///
+    /// The result is backend-agnostic: it can be restored into a repository backed by a
+    /// completely different [`DataStore`] implementation than the one this repository uses.
+    ///
+    /// # Errors
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`clean`]: crate::repo::object::ObjectRepo::clean
+    /// [`import_snapshot`]: crate::repo::object::ObjectRepo::import_snapshot
+    pub fn export_snapshot<W: Write + Seek>(&self, mut writer: W) -> crate::Result<()> {
+        let committed_header = self.read_committed_header()?;
+
+        let mut referenced_blocks = self
+            .state
+            .chunks
+            .values()
+            .map(|info| info.block_id)
+            .collect::<HashSet<_>>();
+        referenced_blocks.extend(committed_header.chunks.values().map(|info| info.block_id));
+        for stripe in self.state.stripes.iter().chain(committed_header.stripes.iter()) {
+            referenced_blocks.extend(stripe.data_blocks.iter().copied());
+            referenced_blocks.extend(stripe.parity_blocks.iter().copied());
+        }
+        referenced_blocks.insert(METADATA_BLOCK_ID);
+        referenced_blocks.insert(VERSION_BLOCK_ID);
+        referenced_blocks.insert(self.state.metadata.header_id);
+
+        let mut block_ids = referenced_blocks.into_iter().collect::<Vec<_>>();
+        block_ids.sort();
+
+        let mut entries = Vec::with_capacity(block_ids.len());
+        let mut offset = 0u64;
+        {
+            let mut store = self.state.store.lock().unwrap();
+            for block_id in block_ids {
+                let data = store
+                    .read_block(block_id)
+                    .map_err(|error| crate::Error::Store(error))?
+                    .ok_or(crate::Error::Corrupt)?;
+                writer.write_all(&(data.len() as u64).to_le_bytes())?;
+                offset += 8;
+                writer.write_all(&data)?;
+                entries.push(SnapshotEntry {
+                    block_id,
+                    offset,
+                    length: data.len() as u64,
+                });
+                offset += data.len() as u64;
+            }
+        }
+
+        let table_offset = offset;
+        let serialized_table =
+            to_vec(&entries).expect("Could not serialize the snapshot offset table.");
+        writer.write_all(&serialized_table)?;
+
+        let footer = SnapshotFooter {
+            table_offset,
+            table_length: serialized_table.len() as u64,
+        };
+        let serialized_footer = to_vec(&footer).expect("Could not serialize the snapshot footer.");
+        writer.write_all(&serialized_footer)?;
+        writer.write_all(&(serialized_footer.len() as u64).to_le_bytes())?;
+
+        Ok(())
+    }
+
+    /// Import a snapshot written by [`export_snapshot`], replacing the contents of this
+    /// repository's backing data store with the ones it contains.
+    ///
+    /// The caller is expected to have already created or opened a repository against the target
+    /// data store (for example with [`OpenRepo::create_repo`]) before calling this; doing so
+    /// establishes the store this method writes into, the same way [`restore`] replaces this
+    /// repository's state in place rather than returning a new one.
+    ///
+    /// Every block except the metadata and version blocks is validated before being written: its
+    /// ciphertext is checked the same way
+    /// [`verify`] checks it, and blocks which store a known chunk additionally have their content
+    /// hash checked against the chunk they're supposed to contain. Set `opts.cancel_token` and
+    /// flip it from another thread to abort the import between blocks; a cancelled import returns
+    /// `Error::Cancelled` having written only the blocks validated so far, so it should only be
+    /// retried against a store that is otherwise known to be empty.
+    ///
+    /// # Errors
+    /// - `Error::Cancelled`: `opts.cancel_token` was set before the import finished.
+    /// - `Error::Corrupt`: The snapshot is truncated, malformed, or one of its blocks fails
+    /// validation.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`export_snapshot`]: crate::repo::object::ObjectRepo::export_snapshot
+    /// [`restore`]: crate::repo::object::ObjectRepo::restore
+    /// [`verify`]: crate::repo::object::ObjectRepo::verify
+    pub fn import_snapshot<R: Read + Seek>(
+        &mut self,
+        mut reader: R,
+        opts: ImportOptions,
+    ) -> crate::Result<()> {
+        reader.seek(SeekFrom::End(-8))?;
+        let mut footer_len_bytes = [0u8; 8];
+        reader.read_exact(&mut footer_len_bytes)?;
+        let footer_len = u64::from_le_bytes(footer_len_bytes);
+
+        reader.seek(SeekFrom::End(-8 - footer_len as i64))?;
+        let mut footer_bytes = vec![0u8; footer_len as usize];
+        reader.read_exact(&mut footer_bytes)?;
+        let footer: SnapshotFooter =
+            from_read(footer_bytes.as_slice()).map_err(|_| crate::Error::Corrupt)?;
+
+        reader.seek(SeekFrom::Start(footer.table_offset))?;
+        let mut table_bytes = vec![0u8; footer.table_length as usize];
+        reader.read_exact(&mut table_bytes)?;
+        let entries: Vec<SnapshotEntry> =
+            from_read(table_bytes.as_slice()).map_err(|_| crate::Error::Corrupt)?;
+
+        // The metadata block tells us which block holds the header, so read it first.
+        let metadata_entry = entries
+            .iter()
+            .find(|entry| entry.block_id == METADATA_BLOCK_ID)
+            .ok_or(crate::Error::Corrupt)?;
+        reader.seek(SeekFrom::Start(metadata_entry.offset))?;
+        let mut metadata_bytes = vec![0u8; metadata_entry.length as usize];
+        reader.read_exact(&mut metadata_bytes)?;
+        self.state.metadata =
+            from_read(metadata_bytes.as_slice()).map_err(|_| crate::Error::Corrupt)?;
+
+        let header_id = self.state.metadata.header_id;
+        let header_entry = entries
+            .iter()
+            .find(|entry| entry.block_id == header_id)
+            .ok_or(crate::Error::Corrupt)?;
+        reader.seek(SeekFrom::Start(header_entry.offset))?;
+        let mut encoded_header = vec![0u8; header_entry.length as usize];
+        reader.read_exact(&mut encoded_header)?;
+        let serialized_header = self.state.decode_data(encoded_header.as_slice())?;
+        let header: Header =
+            from_read(serialized_header.as_slice()).map_err(|_| crate::Error::Corrupt)?;
+
+        for entry in &entries {
+            if opts.cancel_token.load(Ordering::Relaxed) {
+                return Err(crate::Error::Cancelled);
+            }
+
+            reader.seek(SeekFrom::Start(entry.offset))?;
+            let mut data = vec![0u8; entry.length as usize];
+            reader.read_exact(&mut data)?;
+
+            // Validate ciphertext for every block except the metadata and version blocks, and the
+            // content hash for blocks known to store a chunk, before writing it into the target
+            // store.
+            if entry.block_id != METADATA_BLOCK_ID && entry.block_id != VERSION_BLOCK_ID {
+                let decoded = self.state.decode_data(data.as_slice())?;
+                let matching_chunk = header
+                    .chunks
+                    .iter()
+                    .find(|(_, info)| info.block_id == entry.block_id)
+                    .map(|(chunk, _)| *chunk);
+                if let Some(chunk) = matching_chunk {
+                    if decoded.len() != chunk.size as usize || chunk_hash(&decoded) != chunk.hash {
+                        return Err(crate::Error::Corrupt);
+                    }
+                }
+            }
+
+            self.state
+                .store
+                .lock()
+                .unwrap()
+                .write_block(entry.block_id, data.as_slice())
+                .map_err(|error| crate::Error::Store(error))?;
+        }
+
+        self.restore_header(header);
+
+        Ok(())
+    }
+}
+
+/// Measures how long each unit of work takes and sleeps a proportional amount afterward, to cap
+/// the I/O duty cycle of a background task.
+///
+/// This is the throttling strategy [`MaintenanceWorker`] uses between batches of work: after each
+/// batch, it sleeps `elapsed * tranquility` before starting the next one, so a `tranquility` of
+/// `1.0` spends half its time working and half sleeping, while `0.0` disables throttling entirely.
+struct Tranquilizer {
+    tranquility: f64,
+    last_tick: Instant,
+}
+
+impl Tranquilizer {
+    fn new(tranquility: f64) -> Self {
+        Self {
+            tranquility: tranquility.max(0.0),
+            last_tick: Instant::now(),
+        }
+    }
+
+    /// Sleep in proportion to the time elapsed since the last call to `tick`, then reset the
+    /// clock for the next batch of work.
+    fn tick(&mut self) {
+        let elapsed = self.last_tick.elapsed();
+        let sleep_for = elapsed.mul_f64(self.tranquility);
+        if sleep_for > Duration::from_millis(0) {
+            thread::sleep(sleep_for);
+        }
+        self.last_tick = Instant::now();
+    }
+}
+
+/// How frequently a [`MaintenanceWorker`] runs [`verify_with`] and [`clean_with`], and how much it
+/// throttles its own I/O while doing so.
+///
+/// [`verify_with`]: crate::repo::object::ObjectRepo::verify_with
+/// [`clean_with`]: crate::repo::object::ObjectRepo::clean_with
+#[derive(Debug, Clone)]
+pub struct MaintenanceConfig {
+    /// The minimum time to wait between the end of one `verify_with` pass and the start of the
+    /// next.
+    pub verify_interval: Duration,
+
+    /// The minimum time to wait between the end of one `clean_with` pass and the start of the
+    /// next.
+    pub clean_interval: Duration,
+
+    /// The sleep-to-work ratio used to throttle both passes. See [`Tranquilizer`] for how this is
+    /// applied.
+    pub tranquility: f64,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            verify_interval: Duration::from_secs(24 * 60 * 60),
+            clean_interval: Duration::from_secs(6 * 60 * 60),
+            tranquility: 1.0,
+        }
+    }
+}
+
+/// A result reported by a [`MaintenanceWorker`] after completing a scheduled task.
+#[derive(Debug)]
+pub enum MaintenanceReport {
+    /// A `verify_with` pass completed, producing this report.
+    Verified(IntegrityReport),
+
+    /// A `clean_with` pass completed, reclaiming this many bytes from the data store.
+    Cleaned {
+        /// The number of bytes freed from the data store by this pass.
+        bytes_reclaimed: u64,
+    },
+}
+
+/// Sum the size of every data block currently in the repository's backing data store.
+///
+/// This is used by [`MaintenanceWorker`] to measure how many bytes a `clean_with` pass reclaimed,
+/// by comparing the total before and after.
+fn total_data_bytes(repo: &ObjectRepo) -> crate::Result<u64> {
+    let block_ids = repo.list_data_blocks()?;
+    let store = repo.state.store.lock().unwrap();
+
+    let mut total = 0u64;
+    for block_id in block_ids {
+        if let Some(data) = store
+            .read_block(block_id)
+            .map_err(|error| crate::Error::Store(error))?
+        {
+            total += data.len() as u64;
+        }
+    }
+
+    Ok(total)
+}
+
+/// Truncate a shard recovered by [`ObjectRepo::repair`] back to the block's real length, if one
+/// is known.
+///
+/// Data shards are zero-padded out to the stripe's shard length before Reed-Solomon encoding, so
+/// a freshly reconstructed data block carries that padding as trailing bytes; this undoes it.
+/// Parity blocks have no recorded length and are returned unchanged.
+fn truncate_recovered_shard(mut shard: Vec<u8>, original_len: Option<u64>) -> Vec<u8> {
+    if let Some(original_len) = original_len {
+        shard.truncate(original_len as usize);
+    }
+    shard
+}
+
+/// A handle to a background thread which periodically runs [`ObjectRepo::verify_with`] and
+/// [`ObjectRepo::clean_with`] on a repository.
+///
+/// This is meant for a long-lived process using a repository as a service, where integrity checks
+/// and space reclamation should happen on a schedule without saturating the disk with I/O.
+/// Dropping or [`stop`](MaintenanceWorker::stop)ping the worker signals its background thread to
+/// exit as soon as its current batch of work finishes.
+pub struct MaintenanceWorker {
+    stop_token: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl MaintenanceWorker {
+    /// Spawn a background thread which runs `verify_with` and `clean_with` on `repo` according to
+    /// `config`, reporting the result of each completed pass through the returned channel.
+    ///
+    /// The worker takes ownership of `repo` for the lifetime of the background thread, so `repo`
+    /// should be an instance dedicated to maintenance, separate from one used for foreground reads
+    /// and writes.
+    pub fn spawn(
+        mut repo: ObjectRepo,
+        config: MaintenanceConfig,
+    ) -> (Self, mpsc::Receiver<MaintenanceReport>) {
+        let stop_token = Arc::new(AtomicBool::new(false));
+        let worker_stop = Arc::clone(&stop_token);
+        let (report_tx, report_rx) = mpsc::channel();
+
+        let handle = thread::spawn(move || {
+            let now = Instant::now();
+            let mut next_verify = now;
+            let mut next_clean = now;
+
+            while !worker_stop.load(Ordering::Relaxed) {
+                let now = Instant::now();
+
+                if now >= next_verify {
+                    let mut tranquilizer = Tranquilizer::new(config.tranquility);
+                    let verify_opts = VerifyOptions {
+                        cancel_token: Arc::clone(&worker_stop),
+                        ..VerifyOptions::default()
+                    };
+                    match repo.verify_with(verify_opts, |_| tranquilizer.tick()) {
+                        Ok(report) => {
+                            let _ = report_tx.send(MaintenanceReport::Verified(report));
+                        }
+                        Err(crate::Error::Cancelled) => break,
+                        // There's no caller left to hand this error to; the next scheduled pass
+                        // will simply try again.
+                        Err(_) => {}
+                    }
+                    next_verify = Instant::now() + config.verify_interval;
+                }
+
+                if worker_stop.load(Ordering::Relaxed) {
+                    break;
+                }
+
+                if now >= next_clean {
+                    let bytes_before = total_data_bytes(&repo).unwrap_or(0);
+                    let mut tranquilizer = Tranquilizer::new(config.tranquility);
+                    let clean_opts = CleanOptions {
+                        cancel_token: Arc::clone(&worker_stop),
+                    };
+                    match repo.clean_with(clean_opts, |_| tranquilizer.tick()) {
+                        Ok(()) => {
+                            let bytes_after = total_data_bytes(&repo).unwrap_or(bytes_before);
+                            let bytes_reclaimed = bytes_before.saturating_sub(bytes_after);
+                            let _ =
+                                report_tx.send(MaintenanceReport::Cleaned { bytes_reclaimed });
+                        }
+                        Err(crate::Error::Cancelled) => break,
+                        Err(_) => {}
+                    }
+                    next_clean = Instant::now() + config.clean_interval;
+                }
+
+                // Sleep in short increments so the stop signal is noticed promptly even when both
+                // intervals are far in the future.
+                thread::sleep(Duration::from_millis(100));
+            }
+        });
+
+        (
+            Self {
+                stop_token,
+                handle: Some(handle),
+            },
+            report_rx,
+        )
+    }
+
+    /// Signal the background thread to stop after its current batch of work finishes, and block
+    /// until it exits.
+    pub fn stop(mut self) {
+        self.stop_token.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for MaintenanceWorker {
+    fn drop(&mut self) {
+        self.stop_token.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncate_recovered_shard_truncates_a_padded_data_block() {
+        let padded = vec![1, 2, 3, 0, 0, 0, 0, 0];
+        let truncated = truncate_recovered_shard(padded, Some(3));
+        assert_eq!(truncated, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn truncate_recovered_shard_leaves_a_parity_block_unchanged() {
+        let parity = vec![1, 2, 3, 4, 5, 6, 7, 8];
+        let untouched = truncate_recovered_shard(parity.clone(), None);
+        assert_eq!(untouched, parity);
+    }
+
+    #[test]
+    fn truncate_recovered_shard_is_a_no_op_when_already_the_right_length() {
+        let exact = vec![9, 9, 9];
+        let result = truncate_recovered_shard(exact.clone(), Some(3));
+        assert_eq!(result, exact);
+    }
+
+    /// Exercises the full encode → lose a block → reconstruct → truncate pipeline `emit_parity`
+    /// and `repair` drive, with data blocks of different lengths (the normal case under
+    /// `Packing::None`, where each block is a variable-size per-chunk ciphertext).
+    #[test]
+    fn repair_recovers_a_lost_data_block_of_different_length_than_its_stripe_mates() {
+        let original_blocks: Vec<Vec<u8>> = vec![
+            b"a short block".to_vec(),
+            b"a somewhat longer block of data".to_vec(),
+            b"x".to_vec(),
+        ];
+        let data_block_lengths = original_blocks.iter().map(|b| b.len() as u64).collect::<Vec<_>>();
+
+        // Mirror `emit_parity`: pad every data shard out to the longest one before encoding.
+        let shard_len = original_blocks.iter().map(Vec::len).max().unwrap();
+        let mut padded_blocks = original_blocks.clone();
+        for block in &mut padded_blocks {
+            block.resize(shard_len, 0);
+        }
+
+        let code = RsCode::new(original_blocks.len(), 1);
+        let parity = code.encode(&padded_blocks).unwrap();
+
+        // Simulate losing the middle data block.
+        let lost_index = 1;
+        let mut shards: Vec<Option<Vec<u8>>> = padded_blocks.iter().cloned().map(Some).collect();
+        shards.extend(parity.into_iter().map(Some));
+        shards[lost_index] = None;
+
+        code.reconstruct(&mut shards).unwrap();
+
+        // Mirror `repair`: truncate the reconstructed data block back to its real length before
+        // treating it as recovered.
+        let recovered = shards[lost_index].take().unwrap();
+        let recovered = truncate_recovered_shard(recovered, Some(data_block_lengths[lost_index]));
+
+        assert_eq!(recovered, original_blocks[lost_index]);
+    }
 }
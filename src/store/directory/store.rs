@@ -14,18 +14,35 @@
  * limitations under the License.
  */
 
-use std::fs::{create_dir_all, File, remove_dir_all, remove_file, rename};
+use std::fs::{create_dir_all, File, remove_file, rename};
 use std::io::{self, Read, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use uuid::Uuid;
 use walkdir::WalkDir;
 
+use crate::store::security::Trust;
 use crate::store::DataStore;
 
 /// A UUID which acts as the version ID of the directory store format.
 const CURRENT_VERSION: &str = "2891c3da-297e-11ea-a7c9-1b8f8be4fc9b";
 
+/// Convert the error from a failed `Trust::verify` call into the `io::Result` returned by
+/// `DirectoryStore::open_checked` and `DirectoryStore::create_checked`.
+fn trust_error_to_io(error: crate::Error) -> io::Error {
+    match error {
+        crate::Error::Io(io_error) => io_error,
+        crate::Error::Insecure(path) => io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "refusing to trust directory store: `{}` is writable or owned by another user",
+                path.display()
+            ),
+        ),
+        other => io::Error::new(io::ErrorKind::Other, other.to_string()),
+    }
+}
+
 /// A `DataStore` which stores data in a directory in the local file system.
 pub struct DirectoryStore {
     /// The path of the store's root directory.
@@ -45,9 +62,9 @@ impl DirectoryStore {
     /// - `ErrorKind::AlreadyExists`: There is already a file at the given path.
     /// - `ErrorKind::PermissionDenied`: The user lacks permissions to create the directory.
     pub fn create(path: PathBuf) -> io::Result<Self> {
-        create_dir_all(path)?;
+        create_dir_all(&path)?;
         let mut version_file = File::create(path.join("version"))?;
-        version_file.write_all(CURRENT_VERSION.as_bytes());
+        version_file.write_all(CURRENT_VERSION.as_bytes())?;
         Self::open(path)
     }
 
@@ -76,6 +93,53 @@ impl DirectoryStore {
         })
     }
 
+    /// Create a new directory store at `path`, verifying the permissions of its directories.
+    ///
+    /// This creates the store the same way as `create`, but additionally verifies `path` and the
+    /// `blocks` and `stage` subdirectories with [`Trust`] once they exist.
+    ///
+    /// # Errors
+    /// - `ErrorKind::PermissionDenied`: A directory backing the store has insecure permissions.
+    /// - `ErrorKind::AlreadyExists`: There is already a file at the given path.
+    /// - `ErrorKind::PermissionDenied`: The user lacks permissions to create the directory.
+    pub fn create_checked(path: PathBuf) -> io::Result<Self> {
+        create_dir_all(&path)?;
+        let blocks_directory = path.join("blocks");
+        let staging_directory = path.join("stage");
+        create_dir_all(&blocks_directory)?;
+        create_dir_all(&staging_directory)?;
+
+        let trust = Trust::new();
+        trust.verify(&path).map_err(trust_error_to_io)?;
+        trust.verify(&blocks_directory).map_err(trust_error_to_io)?;
+        trust.verify(&staging_directory).map_err(trust_error_to_io)?;
+
+        let mut version_file = File::create(path.join("version"))?;
+        version_file.write_all(CURRENT_VERSION.as_bytes())?;
+
+        Self::open(path)
+    }
+
+    /// Open an existing directory store at `path`, verifying the permissions of its directories.
+    ///
+    /// This opens the store the same way as `open`, but additionally verifies `path` and the
+    /// `blocks` and `stage` subdirectories with [`Trust`] before returning it. Set the
+    /// `ACID_STORE_DISABLE_TRUST_CHECK` environment variable to skip these checks entirely, for
+    /// CI or container environments that run as root with a permissive umask.
+    ///
+    /// # Errors
+    /// - `ErrorKind::PermissionDenied`: A directory backing the store has insecure permissions.
+    /// - `ErrorKind::NotFound`: There is not a directory at `path`.
+    /// - `ErrorKind::InvalidData`: The directory at `path` is not a valid directory store.
+    pub fn open_checked(path: PathBuf) -> io::Result<Self> {
+        let trust = Trust::new();
+        trust.verify(&path).map_err(trust_error_to_io)?;
+        let store = Self::open(path)?;
+        trust.verify(&store.blocks_directory).map_err(trust_error_to_io)?;
+        trust.verify(&store.staging_directory).map_err(trust_error_to_io)?;
+        Ok(store)
+    }
+
     /// Return the path where a block with the given `id` will be stored.
     fn block_path(&self, id: &Uuid) -> PathBuf {
         let hex = id.to_simple().encode_lower(&mut Uuid::encode_buffer());
@@ -96,13 +160,14 @@ impl DataStore for DirectoryStore {
         create_dir_all(staging_path.parent().unwrap())?;
         create_dir_all(block_path.parent().unwrap())?;
 
-        // Write to a staging file and then atomically move it to its final destination.
-        let mut staging_file = File::create(staging_path)?;
+        // Write to a staging file and then atomically move it to its final destination. Once the
+        // rename succeeds, there's nothing left in the staging directory for this block, so there
+        // is no cleanup to do here; sweeping the whole staging directory on every write would
+        // race with any other write still in progress.
+        let mut staging_file = File::create(&staging_path)?;
         staging_file.write_all(data)?;
-        rename(staging_path, block_path)?;
-
-        // Remove any unused staging files.
-        remove_dir_all(self.staging_directory)?;
+        staging_file.sync_all()?;
+        rename(&staging_path, &block_path)?;
 
         Ok(())
     }
@@ -110,13 +175,17 @@ impl DataStore for DirectoryStore {
     fn read_block(&self, id: &Uuid) -> io::Result<Vec<u8>> {
         let block_path = self.block_path(id);
 
-        if block_path.exists() {
-            let mut file = File::open(block_path)?;
-            let mut buffer = Vec::with_capacity(file.metadata()?.len() as usize);
-            file.read_to_end(&mut buffer)?;
-            Ok(buffer)
-        } else {
-            panic!("There is no block with the given ID.")
+        match File::open(&block_path) {
+            Ok(mut file) => {
+                let mut buffer = Vec::with_capacity(file.metadata()?.len() as usize);
+                file.read_to_end(&mut buffer)?;
+                Ok(buffer)
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("there is no block with ID `{}`", id),
+            )),
+            Err(error) => Err(error),
         }
     }
 
@@ -129,16 +198,88 @@ impl DataStore for DirectoryStore {
             WalkDir::new(self.blocks_directory)
                 .min_depth(2)
                 .into_iter()
-                .map(|result| match result {
-                    Ok(entry) => Ok(Uuid::parse_str(
-                        entry
-                            .file_name()
-                            .to_str()
-                            .expect("Block file name is invalid."),
-                    )
-                        .expect("Block file name is invalid.")),
-                    Err(error) => Err(io::Error::from(error)),
+                .map(|result| {
+                    let entry = result.map_err(io::Error::from)?;
+                    parse_block_id(&entry)
                 }),
         ))
     }
 }
+
+/// Parse the block ID encoded in a `WalkDir` entry's file name.
+///
+/// A malformed or unparsable file name is surfaced as an `ErrorKind::InvalidData` error rather
+/// than panicking, so a single corrupt entry doesn't bring down the whole listing.
+fn parse_block_id(entry: &walkdir::DirEntry) -> io::Result<Uuid> {
+    let file_name = entry.file_name().to_str().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "block file name is not valid UTF-8")
+    })?;
+
+    Uuid::parse_str(file_name)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "block file name is not a valid UUID"))
+}
+
+impl DirectoryStore {
+    /// Walk every block in this store and check that it is present and readable.
+    ///
+    /// This does not verify content integrity beyond successfully reading each block, since block
+    /// IDs in a `DirectoryStore` are caller-assigned rather than derived from content; it catches
+    /// missing blocks and I/O-level corruption such as truncated files.
+    ///
+    /// # Errors
+    /// `ErrorKind::InvalidData` if a block's file name can't be parsed as a UUID, or any error
+    /// from walking the blocks directory. An error reading a block is instead recorded against
+    /// that block's ID in the returned list.
+    pub fn verify(&self) -> io::Result<Vec<Uuid>> {
+        let mut corrupt = Vec::new();
+
+        for result in self.list_blocks()? {
+            let id = result?;
+            if self.read_block(&id).is_err() {
+                corrupt.push(id);
+            }
+        }
+
+        Ok(corrupt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::Permissions;
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    #[test]
+    fn create_checked_succeeds_for_a_directory_only_the_current_user_can_write_to() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::set_permissions(temp_dir.path(), Permissions::from_mode(0o700)).unwrap();
+
+        let store_path = temp_dir.path().join("store");
+        assert!(DirectoryStore::create_checked(store_path).is_ok());
+    }
+
+    #[test]
+    fn create_checked_rejects_a_parent_directory_writable_by_everyone() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::set_permissions(temp_dir.path(), Permissions::from_mode(0o777)).unwrap();
+
+        let store_path = temp_dir.path().join("store");
+        let error = DirectoryStore::create_checked(store_path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn open_checked_rejects_a_store_directory_writable_by_everyone() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::set_permissions(temp_dir.path(), Permissions::from_mode(0o700)).unwrap();
+
+        let store_path = temp_dir.path().join("store");
+        DirectoryStore::create(store_path.clone()).unwrap();
+        std::fs::set_permissions(&store_path, Permissions::from_mode(0o777)).unwrap();
+
+        let error = DirectoryStore::open_checked(store_path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+    }
+}
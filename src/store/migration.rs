@@ -0,0 +1,146 @@
+/*
+ * Copyright 2019-2020 Wren Powell
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! In-place migration of a store's on-disk format across crate versions.
+//!
+//! [`check_version`] compares the [`FormatVersion`] recorded in a store's `BlockKey::Version`
+//! block against [`CURRENT_FORMAT_VERSION`], and refuses a store from a newer crate version. When
+//! a store predates the current format, [`upgrade`] walks an ordered chain of [`Migration`] steps
+//! to bring it up to date in place.
+//!
+//! [`upgrade`] is the only code that records a version: creating a store doesn't write one, and
+//! neither `OpenStore::open` nor anything else in this crate calls [`check_version`] or
+//! [`upgrade`]. Both run only when a caller invokes them, for example from a dedicated CLI
+//! subcommand, so a store is never rewritten as a side effect of simply opening it.
+
+use std::cmp::Ordering;
+
+use rmp_serde::{from_slice, to_vec};
+use serde::{Deserialize, Serialize};
+
+use super::data_store::{BlockKey, DataStore};
+use crate::error::{Error, Result};
+
+/// The on-disk format version understood by this version of the crate.
+pub const CURRENT_FORMAT_VERSION: FormatVersion = FormatVersion(1);
+
+/// A version of a store's on-disk format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct FormatVersion(pub u32);
+
+/// A single step in migrating a store from one format version to the next.
+///
+/// Migrations are applied in a chain: each one's [`from_version`] must match the previous
+/// migration's [`to_version`] (or the store's current version, for the first migration applied),
+/// and the last migration's `to_version` must equal [`CURRENT_FORMAT_VERSION`].
+///
+/// [`from_version`]: Migration::from_version
+/// [`to_version`]: Migration::to_version
+pub trait Migration {
+    /// The format version this migration upgrades a store from.
+    fn from_version(&self) -> FormatVersion;
+
+    /// The format version this migration upgrades a store to.
+    fn to_version(&self) -> FormatVersion;
+
+    /// Rewrite whatever headers or blocks changed shape between `from_version` and `to_version`.
+    ///
+    /// # Errors
+    /// - `Error::Store`: An error occurred with the data store.
+    fn migrate(&self, store: &mut dyn DataStore) -> Result<()>;
+}
+
+/// Return the ordered chain of migrations from the oldest version this crate can open up to
+/// [`CURRENT_FORMAT_VERSION`].
+///
+/// New migrations are registered here as the format evolves; this list currently has no entries
+/// because this is the first format version that's aware of migrations.
+fn registered_migrations() -> Vec<Box<dyn Migration>> {
+    Vec::new()
+}
+
+/// Read the format version recorded in `store`, or `None` if it predates format versioning.
+fn read_version(store: &mut dyn DataStore) -> Result<Option<FormatVersion>> {
+    let mut buffer = Vec::new();
+    match store
+        .read_block(BlockKey::Version, &mut buffer)
+        .map_err(Error::Store)?
+    {
+        Some(_) => Ok(Some(from_slice(&buffer).map_err(|_| Error::Corrupt)?)),
+        None => Ok(None),
+    }
+}
+
+/// Write `version` to the `BlockKey::Version` block in `store`.
+fn write_version(store: &mut dyn DataStore, version: FormatVersion) -> Result<()> {
+    let serialized = to_vec(&version).expect("Could not serialize the format version.");
+    store
+        .write_block(BlockKey::Version, &serialized)
+        .map_err(Error::Store)
+}
+
+/// Verify that `store`'s on-disk format can be opened by this version of the crate.
+///
+/// This is the check every `OpenStore::open` implementation should run before reading anything
+/// else. It never migrates or modifies the store; a store that records a version behind the
+/// current format fails this check until a caller explicitly runs [`upgrade`]. A store with no
+/// recorded version passes it.
+///
+/// # Errors
+/// - `Error::UnsupportedFormat`: The store's format is newer than this version of the crate
+/// understands.
+/// - `Error::OutOfDate`: The store's format predates `CURRENT_FORMAT_VERSION` and must be
+/// migrated with [`upgrade`] before it can be opened.
+/// - `Error::Store`: An error occurred with the data store.
+pub fn check_version(store: &mut dyn DataStore) -> Result<()> {
+    match read_version(store)? {
+        None => Ok(()),
+        Some(version) => match version.cmp(&CURRENT_FORMAT_VERSION) {
+            Ordering::Greater => Err(Error::UnsupportedFormat),
+            Ordering::Less => Err(Error::OutOfDate),
+            Ordering::Equal => Ok(()),
+        },
+    }
+}
+
+/// Bring `store` up to `CURRENT_FORMAT_VERSION`, running whichever migrations are needed.
+///
+/// A store with no recorded version is treated as predating format versioning, i.e. version
+/// `0`. This is the entry point a CLI's `upgrade` command should call; it is never invoked
+/// implicitly by `OpenStore::open`.
+///
+/// # Errors
+/// - `Error::UnsupportedFormat`: The store's format is newer than this version of the crate
+/// understands.
+/// - `Error::Store`: An error occurred with the data store.
+pub fn upgrade(store: &mut dyn DataStore) -> Result<()> {
+    let mut version = read_version(store)?.unwrap_or(FormatVersion(0));
+
+    if version > CURRENT_FORMAT_VERSION {
+        return Err(Error::UnsupportedFormat);
+    }
+
+    for migration in registered_migrations() {
+        if migration.from_version() != version {
+            continue;
+        }
+        migration.migrate(store)?;
+        version = migration.to_version();
+        write_version(store, version)?;
+    }
+
+    write_version(store, version)
+}
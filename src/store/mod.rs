@@ -28,20 +28,30 @@
 //! ).unwrap();
 //! ```
 
+pub use self::async_store::{AsyncDataStore, AsyncDirectoryStore, BlockIdStream};
+#[cfg(all(feature = "store-async", feature = "store-redis"))]
+pub use self::async_store::AsyncRedisStore;
+#[cfg(all(feature = "store-async", feature = "store-s3"))]
+pub use self::async_store::AsyncS3Store;
 pub use self::common::{DataStore, Open, OpenOption};
 #[cfg(feature = "store-directory")]
 pub use self::directory::DirectoryStore;
 pub use self::memory::MemoryStore;
+pub use self::migration::{check_version, upgrade, FormatVersion, Migration, CURRENT_FORMAT_VERSION};
 #[cfg(feature = "store-redis")]
 pub use self::redis::RedisStore;
 #[cfg(feature = "store-s3")]
 pub use self::s3::S3Store;
+pub use self::security::{Trust, DISABLE_TRUST_CHECK_VAR};
 #[cfg(feature = "store-sqlite")]
 pub use self::sqlite::SqliteStore;
 
+mod async_store;
 mod common;
 mod directory;
 mod memory;
+mod migration;
 mod redis;
 mod s3;
+mod security;
 mod sqlite;
@@ -0,0 +1,217 @@
+/*
+ * Copyright 2019-2020 Wren Powell
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Permission hardening for filesystem-backed stores.
+//!
+//! A store which is encrypted protects data at rest, but if the directory backing it (or one of
+//! its ancestors) is writable by another local user, that user can replace its files out from
+//! under the process that opened it. [`Trust`] walks a canonicalized path up to a configured
+//! boundary and verifies that no component is writable by anyone but the current user.
+
+use std::env;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+
+/// The environment variable that disables the checks performed by [`Trust::verify`].
+///
+/// This is an escape hatch for CI and container environments that run as root with a permissive
+/// umask, where the checks below would otherwise always fail.
+pub const DISABLE_TRUST_CHECK_VAR: &str = "ACID_STORE_DISABLE_TRUST_CHECK";
+
+/// A policy for verifying that the directory backing a store has not been tampered with.
+///
+/// By default, a `Trust` walks every ancestor of the path being checked up to the filesystem
+/// root. Use [`with_boundary`] to stop at a directory the caller already trusts, and
+/// [`insecure`] or the [`DISABLE_TRUST_CHECK_VAR`] environment variable to opt out entirely.
+///
+/// [`with_boundary`]: Trust::with_boundary
+/// [`insecure`]: Trust::insecure
+#[derive(Debug, Clone)]
+pub struct Trust {
+    /// The highest ancestor directory to check, inclusive.
+    ///
+    /// If `None`, the check walks all the way up to the filesystem root.
+    boundary: Option<PathBuf>,
+
+    /// Whether checks are skipped entirely.
+    disabled: bool,
+}
+
+impl Default for Trust {
+    fn default() -> Self {
+        Self {
+            boundary: None,
+            disabled: env::var_os(DISABLE_TRUST_CHECK_VAR).is_some(),
+        }
+    }
+}
+
+impl Trust {
+    /// Create a new `Trust` policy using the default boundary (the filesystem root).
+    ///
+    /// This respects [`DISABLE_TRUST_CHECK_VAR`] even when not explicitly disabled by the caller.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stop checking ancestors once `boundary` has been checked.
+    ///
+    /// This is useful when a store lives under a directory the caller already trusts, such as a
+    /// user's home directory, so that unrelated parent directories aren't inspected.
+    pub fn with_boundary(mut self, boundary: impl Into<PathBuf>) -> Self {
+        self.boundary = Some(boundary.into());
+        self
+    }
+
+    /// Disable the checks performed by [`verify`], regardless of the environment.
+    ///
+    /// [`verify`]: Trust::verify
+    pub fn insecure(mut self) -> Self {
+        self.disabled = true;
+        self
+    }
+
+    /// Verify that `path` and its ancestors up to the configured boundary are not writable by any
+    /// principal other than the current user.
+    ///
+    /// # Errors
+    /// - `Error::Insecure`: A component of `path` is writable by a user other than the one
+    /// running this process, or is owned by another user entirely.
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn verify(&self, path: &Path) -> Result<()> {
+        if self.disabled {
+            return Ok(());
+        }
+
+        let canonical = path.canonicalize()?;
+        let current_uid = effective_uid();
+
+        for ancestor in canonical.ancestors() {
+            let metadata = fs::symlink_metadata(ancestor)?;
+            check_component(ancestor, &metadata, current_uid)?;
+
+            if self.boundary.as_deref() == Some(ancestor) {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Return the effective user ID of the current process.
+fn effective_uid() -> u32 {
+    // SAFETY: `geteuid` takes no arguments and always succeeds.
+    unsafe { libc::geteuid() }
+}
+
+/// Check that a single path component is owned by `current_uid` and not writable by anyone else.
+fn check_component(path: &Path, metadata: &fs::Metadata, current_uid: u32) -> Result<()> {
+    let mode = metadata.mode();
+    let owner_uid = metadata.uid();
+
+    // Owned by another user: that user can change the component's permissions at will, so their
+    // ownership alone is enough to let them tamper with us, regardless of the current mode bits.
+    if owner_uid != current_uid {
+        return Err(Error::Insecure(path.to_owned()));
+    }
+
+    // Writable by the owning group or by everyone. A directory with the sticky bit set (e.g.
+    // `/tmp` at `1777`) is exempt: the sticky bit restricts renaming or removing an entry to its
+    // owner (or the directory's owner), so another user with write access still can't replace a
+    // component out from under us.
+    if mode & 0o022 != 0 && mode & 0o1000 == 0 {
+        return Err(Error::Insecure(path.to_owned()));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, Permissions};
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    #[test]
+    fn verify_accepts_a_directory_owned_and_writable_only_by_the_current_user() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        fs::set_permissions(temp_dir.path(), Permissions::from_mode(0o700)).unwrap();
+
+        let trust = Trust::new();
+        assert!(trust.verify(temp_dir.path()).is_ok());
+    }
+
+    #[test]
+    fn verify_rejects_a_directory_writable_by_the_owning_group() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        fs::set_permissions(temp_dir.path(), Permissions::from_mode(0o770)).unwrap();
+
+        let trust = Trust::new();
+        assert!(matches!(trust.verify(temp_dir.path()), Err(Error::Insecure(_))));
+    }
+
+    #[test]
+    fn verify_rejects_a_directory_writable_by_everyone() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        fs::set_permissions(temp_dir.path(), Permissions::from_mode(0o777)).unwrap();
+
+        let trust = Trust::new();
+        assert!(matches!(trust.verify(temp_dir.path()), Err(Error::Insecure(_))));
+    }
+
+    #[test]
+    fn insecure_skips_the_check_entirely() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        fs::set_permissions(temp_dir.path(), Permissions::from_mode(0o777)).unwrap();
+
+        let trust = Trust::new().insecure();
+        assert!(trust.verify(temp_dir.path()).is_ok());
+    }
+
+    #[test]
+    fn with_boundary_stops_walking_ancestors_at_the_boundary() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let nested = temp_dir.path().join("nested");
+        fs::create_dir(&nested).unwrap();
+        fs::set_permissions(&nested, Permissions::from_mode(0o700)).unwrap();
+
+        // An insecure ancestor above the boundary is not checked once the boundary is reached.
+        fs::set_permissions(temp_dir.path(), Permissions::from_mode(0o777)).unwrap();
+
+        let trust = Trust::new().with_boundary(temp_dir.path());
+        assert!(trust.verify(&nested).is_ok());
+    }
+
+    #[test]
+    fn check_component_rejects_ownership_mismatch_even_without_the_write_bit() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        fs::set_permissions(temp_dir.path(), Permissions::from_mode(0o555)).unwrap();
+        let metadata = fs::symlink_metadata(temp_dir.path()).unwrap();
+
+        // Simulate a different owner by checking against a UID that can't be the current one.
+        let other_uid = effective_uid().wrapping_add(1);
+
+        assert!(matches!(
+            check_component(temp_dir.path(), &metadata, other_uid),
+            Err(Error::Insecure(_))
+        ));
+    }
+}
@@ -0,0 +1,310 @@
+/*
+ * Copyright 2019 Garrett Powell
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Tar import and export for `ObjectArchive`.
+//!
+//! `import_tar` is written the way a snapshot unpacker should be: it never trusts the paths or
+//! sizes recorded in the tar stream, since those can come from an untrusted third party. Every
+//! entry's path is sanitized before anything is written, and the import is bounded by running
+//! byte and entry count limits so a malicious or merely corrupt tar can't exhaust memory or disk.
+
+use std::fmt;
+use std::hash::Hash;
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+use tar::{Archive as TarReader, Builder as TarBuilder, EntryType as TarEntryType, Header as TarHeader};
+
+use crate::ObjectArchive;
+
+/// Limits applied while importing an untrusted tar stream with `ObjectArchive::import_tar`.
+#[derive(Debug, Clone, Copy)]
+pub struct UnpackOptions {
+    /// The maximum cumulative number of uncompressed bytes this import will write.
+    ///
+    /// For GNU sparse entries, this is checked against the entry's *apparent* size (including
+    /// holes), not the number of bytes actually written, since a sparse entry can claim to be
+    /// much larger than the data it contains.
+    pub max_total_bytes: u64,
+
+    /// The maximum number of entries this import will materialize.
+    pub max_entries: u64,
+}
+
+impl Default for UnpackOptions {
+    fn default() -> Self {
+        Self {
+            max_total_bytes: 10 * 1024 * 1024 * 1024,
+            max_entries: 1_000_000,
+        }
+    }
+}
+
+/// The reason an `import_tar` call was aborted before finishing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnpackErrorKind {
+    /// An entry's path, or a symlink/hardlink's target, would resolve outside the archive
+    /// namespace.
+    PathEscapesArchive,
+
+    /// The cumulative uncompressed byte count exceeded `UnpackOptions::max_total_bytes`.
+    ByteLimitExceeded,
+
+    /// The number of entries exceeded `UnpackOptions::max_entries`.
+    EntryLimitExceeded,
+}
+
+impl fmt::Display for UnpackErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PathEscapesArchive => {
+                write!(f, "the entry's path or link target escapes the archive")
+            }
+            Self::ByteLimitExceeded => write!(f, "the import exceeded its maximum byte count"),
+            Self::EntryLimitExceeded => write!(f, "the import exceeded its maximum entry count"),
+        }
+    }
+}
+
+impl std::error::Error for UnpackErrorKind {}
+
+/// Reject any path component that could escape the archive's namespace.
+///
+/// Only `Normal` components and a leading `CurDir` are accepted; a `ParentDir`, a root, or a
+/// Windows path prefix are all rejected outright rather than silently stripped.
+///
+/// This is also used by the metadata-preserving archiving subsystem in [`crate::dir_archive`], so
+/// that a stored relative path can never escape the destination root on restore either.
+pub(crate) fn sanitize_entry_path(path: &Path) -> io::Result<PathBuf> {
+    let mut sanitized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => sanitized.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    UnpackErrorKind::PathEscapesArchive,
+                ));
+            }
+        }
+    }
+    Ok(sanitized)
+}
+
+/// Return whether `target`, resolved relative to the directory containing `entry_path`, stays
+/// within the archive namespace.
+///
+/// This walks the target's components lexically, tracking how many directory levels above the
+/// archive root the resolved path would sit; a `ParentDir` component that would walk above the
+/// root is rejected.
+///
+/// This is also used by the metadata-preserving archiving subsystem in [`crate::dir_archive`], so
+/// that a restored symlink can never point outside the destination root.
+pub(crate) fn link_target_is_contained(entry_path: &Path, target: &Path) -> bool {
+    let mut depth: i64 = entry_path
+        .parent()
+        .map(|parent| parent.components().count() as i64)
+        .unwrap_or(0);
+
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir => {
+                depth -= 1;
+                if depth < 0 {
+                    return false;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+
+    true
+}
+
+impl<K> ObjectArchive<K>
+where
+    K: From<String> + AsRef<str> + Eq + Hash + Clone,
+{
+    /// Import entries from the tar stream in `reader`, enforcing `opts`.
+    ///
+    /// Every entry's path is sanitized before it is materialized: components of `..`, an
+    /// absolute path, or a path with a root/prefix are all rejected, and symlink or hardlink
+    /// entries whose target would resolve outside the archive are rejected too. Regular files
+    /// are streamed through `write` to produce an object; directories are skipped, since
+    /// `ObjectArchive` has no directory entries of its own.
+    ///
+    /// # Errors
+    /// - `Error::Io`: An I/O error occurred, including a rejected or oversized entry. The
+    /// underlying `io::Error` carries an `UnpackErrorKind` describing why the entry was
+    /// rejected, when applicable.
+    pub fn import_tar<R: Read>(&mut self, reader: R, opts: UnpackOptions) -> io::Result<()> {
+        let mut tar = TarReader::new(reader);
+
+        let mut total_bytes: u64 = 0;
+        let mut total_entries: u64 = 0;
+
+        for entry_result in tar.entries()? {
+            let mut entry = entry_result?;
+            let header = entry.header().clone();
+            let raw_path = entry.path()?.into_owned();
+            let sanitized = sanitize_entry_path(&raw_path)?;
+
+            if matches!(
+                header.entry_type(),
+                TarEntryType::Symlink | TarEntryType::Link
+            ) {
+                let target = entry.link_name()?.ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "link entry has no target")
+                })?;
+                // A hardlink's target names another archive member, so it resolves from the
+                // archive root; a symlink's target resolves from the entry's own directory.
+                let base = if header.entry_type() == TarEntryType::Link {
+                    Path::new("")
+                } else {
+                    sanitized.as_path()
+                };
+                if !link_target_is_contained(base, &target) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        UnpackErrorKind::PathEscapesArchive,
+                    ));
+                }
+                // `ObjectArchive` only stores opaque byte objects, so there's nothing to
+                // materialize for a link entry once we've confirmed it's safe to skip.
+                continue;
+            }
+
+            if header.entry_type() == TarEntryType::Directory {
+                continue;
+            }
+
+            total_entries += 1;
+            if total_entries > opts.max_entries {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    UnpackErrorKind::EntryLimitExceeded,
+                ));
+            }
+
+            // Track the entry's apparent size (including holes, for GNU sparse entries) rather
+            // than the number of bytes we'll actually write, since a sparse entry can claim to
+            // be far larger than the data backing it.
+            let apparent_size = header.size().unwrap_or(0);
+            total_bytes = total_bytes.saturating_add(apparent_size);
+            if total_bytes > opts.max_total_bytes {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    UnpackErrorKind::ByteLimitExceeded,
+                ));
+            }
+
+            let object = self.write(&mut entry)?;
+            let key = K::from(sanitized.to_string_lossy().into_owned());
+            self.insert(key, object);
+        }
+
+        Ok(())
+    }
+
+    /// Export every object in this archive as a tar stream written to `writer`.
+    ///
+    /// Each key is mapped to a tar path via `AsRef<str>`. Objects are read into memory one at a
+    /// time with `read_all` before being written out, so peak memory use is bounded by the
+    /// largest single object rather than the archive as a whole.
+    ///
+    /// # Errors
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn export_tar<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut builder = TarBuilder::new(writer);
+
+        let mut keys = self.keys().cloned().collect::<Vec<_>>();
+        keys.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+
+        for key in keys {
+            let object = self.get(&key).expect("Key disappeared during export.");
+            let data = self.read_all(object)?;
+
+            let mut header = TarHeader::new_ustar();
+            header.set_size(data.len() as u64);
+            header.set_entry_type(TarEntryType::Regular);
+            header.set_mode(0o644);
+            header.set_cksum();
+
+            builder.append_data(&mut header, key.as_ref(), data.as_slice())?;
+        }
+
+        builder.into_inner()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_entry_path_accepts_relative_paths() {
+        let sanitized = sanitize_entry_path(Path::new("foo/bar.txt")).unwrap();
+        assert_eq!(sanitized, Path::new("foo/bar.txt"));
+    }
+
+    #[test]
+    fn sanitize_entry_path_strips_leading_cur_dir() {
+        let sanitized = sanitize_entry_path(Path::new("./foo/bar.txt")).unwrap();
+        assert_eq!(sanitized, Path::new("foo/bar.txt"));
+    }
+
+    #[test]
+    fn sanitize_entry_path_rejects_parent_dir() {
+        assert!(sanitize_entry_path(Path::new("../escape.txt")).is_err());
+        assert!(sanitize_entry_path(Path::new("foo/../../escape.txt")).is_err());
+    }
+
+    #[test]
+    fn sanitize_entry_path_rejects_absolute_path() {
+        assert!(sanitize_entry_path(Path::new("/etc/passwd")).is_err());
+    }
+
+    #[test]
+    fn link_target_is_contained_accepts_sibling_target() {
+        assert!(link_target_is_contained(Path::new("dir/entry"), Path::new("other")));
+    }
+
+    #[test]
+    fn link_target_is_contained_rejects_target_above_root() {
+        assert!(!link_target_is_contained(Path::new("entry"), Path::new("../escape")));
+    }
+
+    #[test]
+    fn link_target_is_contained_rejects_deeply_nested_escape() {
+        assert!(!link_target_is_contained(
+            Path::new("a/b/entry"),
+            Path::new("../../../escape")
+        ));
+    }
+
+    #[test]
+    fn link_target_is_contained_accepts_descend_then_ascend() {
+        assert!(link_target_is_contained(
+            Path::new("a/entry"),
+            Path::new("sub/../sibling")
+        ));
+    }
+}